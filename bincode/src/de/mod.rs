@@ -263,9 +263,18 @@ where
     where
         V: serde::de::Visitor<'de>,
     {
-        let len = O::IntEncoding::deserialize_len(self)?;
-        self.read_bytes(len as u64)?;
-        self.reader.forward_read_str(len, visitor)
+        // only ANSI FStrings can be borrowed, skip the null terminator after the contents
+        let len = O::IntEncoding::deserialize_i32(self)?;
+        if len == 0 {
+            return visitor.visit_borrowed_str("");
+        }
+        if len < 0 {
+            return Err(ErrorKind::Custom("cannot borrow a UTF-16 string".to_string()).into());
+        }
+        self.read_bytes(len as u64 - 1)?;
+        let value = self.reader.forward_read_str(len as usize - 1, visitor)?;
+        self.deserialize_byte()?;
+        Ok(value)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
@@ -82,7 +82,7 @@ impl<'a, W: Write, O: Options> serde::Serializer for &'a mut Serializer<W, O> {
     }
 
     fn serialize_bool(self, v: bool) -> Result<()> {
-        self.serialize_byte(v as u8)
+        self.serialize_literal_u32(v as u32)
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
@@ -119,8 +119,21 @@ impl<'a, W: Write, O: Options> serde::Serializer for &'a mut Serializer<W, O> {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        O::IntEncoding::serialize_len(self, v.len())?;
-        self.writer.write_all(v.as_bytes()).map_err(Into::into)
+        // FString: length includes the null terminator, negative lengths are UTF-16
+        if v.is_empty() {
+            return O::IntEncoding::serialize_i32(self, 0);
+        }
+        if v.is_ascii() {
+            O::IntEncoding::serialize_i32(self, fstring_len(v.len())?)?;
+            self.writer.write_all(v.as_bytes())?;
+            return self.serialize_byte(0);
+        }
+        let chars: Vec<u16> = v.encode_utf16().collect();
+        O::IntEncoding::serialize_i32(self, -fstring_len(chars.len())?)?;
+        for c in chars {
+            self.serialize_literal_u16(c)?;
+        }
+        self.serialize_literal_u16(0)
     }
 
     fn serialize_char(self, c: char) -> Result<()> {
@@ -283,7 +296,7 @@ impl<'a, O: Options> serde::Serializer for &'a mut SizeChecker<O> {
     }
 
     fn serialize_bool(self, _: bool) -> Result<()> {
-        self.add_raw(1)
+        self.add_raw(4)
     }
 
     fn serialize_u8(self, _: u8) -> Result<()> {
@@ -314,8 +327,14 @@ impl<'a, O: Options> serde::Serializer for &'a mut SizeChecker<O> {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        self.add_len(v.len())?;
-        self.add_raw(v.len() as u64)
+        self.add_raw(4)?;
+        if v.is_empty() {
+            Ok(())
+        } else if v.is_ascii() {
+            self.add_raw(v.len() as u64 + 1)
+        } else {
+            self.add_raw((v.encode_utf16().count() as u64 + 1) * 2)
+        }
     }
 
     fn serialize_char(self, c: char) -> Result<()> {
@@ -727,6 +746,14 @@ impl<'a, O: Options> serde::ser::SerializeStructVariant for SizeCompound<'a, O>
         Ok(())
     }
 }
+/// Length of an FString with `len` characters, counting the null terminator.
+fn fstring_len(len: usize) -> Result<i32> {
+    if len >= i32::MAX as usize {
+        return Err(ErrorKind::SizeLimit.into());
+    }
+    Ok(len as i32 + 1)
+}
+
 const TAG_CONT: u8 = 0b1000_0000;
 const TAG_TWO_B: u8 = 0b1100_0000;
 const TAG_THREE_B: u8 = 0b1110_0000;
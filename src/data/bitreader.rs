@@ -99,11 +99,12 @@ impl<'a> BitReader<'a> {
         let dx = self.read_serialized_int(max)?;
         let dy = self.read_serialized_int(max)?;
         let dz = self.read_serialized_int(max)?;
-        let x = ((dx as f32) - (bias as f32)) / (scale_factor as f32);
-        let y = ((dy as f32) - (bias as f32)) / (scale_factor as f32);
-        let z = ((dz as f32) - (bias as f32)) / (scale_factor as f32);
-        // ^ dont cast as f32 if want to succeed in packet vector tests :)
-        Ok(FVector(x as f32, y as f32, z as f32))
+        // as `ReadPackedVector`, the bias is subtracted before converting since components of up
+        // to 30 bits do not fit an f32
+        let x = (dx as i64 - bias as i64) as f32 / (scale_factor as f32);
+        let y = (dy as i64 - bias as i64) as f32 / (scale_factor as f32);
+        let z = (dz as i64 - bias as i64) as f32 / (scale_factor as f32);
+        Ok(FVector(x, y, z))
     }
 
     pub fn read_conditionally_serialized_quantized_vector(&mut self, default_vector: FVector) -> crate::Result<FVector> {
@@ -131,6 +132,22 @@ impl<'a> BitReader<'a> {
         Ok(FRotator(pitch, yaw, roll))
     }
 
+    pub fn read_rotation_byte(&mut self) -> crate::Result<FRotator> {
+        let mut pitch: f32 = 0 as f32;
+        let mut yaw: f32 = 0 as f32;
+        let mut roll: f32 = 0 as f32;
+        if self.read_bit()? {
+            pitch = self.read_u8()? as f32 * 360.0 / 256.0;
+        }
+        if self.read_bit()? {
+            yaw = self.read_u8()? as f32 * 360.0 / 256.0;
+        }
+        if self.read_bit()? {
+            roll = self.read_u8()? as f32 * 360.0 / 256.0;
+        }
+        Ok(FRotator(pitch, yaw, roll))
+    }
+
 }
 
 impl<'a> Read for BitReader<'a> {
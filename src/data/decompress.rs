@@ -1,21 +1,89 @@
-use crate::ErrorKind;
-use std::ptr::null;
-
-type DecompressFunc = unsafe fn(*const u8, u64, *mut u8, u64, u32, u32, u32, u64, u64, u64, u64, u64, u64, u32) -> i32;
-
-// https://github.com/SirWaddles/JohnWickParse/blob/master/src/decompress/oodle.rs
-pub fn decompress_stream(uncompressed_size: u64, bytes: &[u8]) -> crate::Result<Vec<u8>> {
-    let library = libloading::Library::new("./oo2core_7_win64.dll")?;
-    let mut output = vec![0u8; uncompressed_size as usize];
-    let check;
-    unsafe {
-        let func: libloading::Symbol<DecompressFunc> = library.get(b"OodleLZ_Decompress")?;
-        check = func(bytes.as_ptr(), bytes.len() as u64, output.as_mut_ptr(), uncompressed_size, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0);
+#[cfg(feature = "oodle-library")]
+use std::fmt::{Debug, Formatter};
+#[cfg(feature = "oodle-library")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "oodle-library")]
+type DecompressFunc = unsafe extern "C" fn(*const u8, u64, *mut u8, u64, u32, u32, u32, u64, u64, u64, u64, u64, u64, u32) -> i32;
+
+/// Default location of the Oodle library shipped with the game.
+#[cfg(feature = "oodle-library")]
+pub const OODLE_DLL_PATH: &str = "./oo2core_7_win64.dll";
+
+/// Decompresses the payload of a replay data chunk, only used for replays whose
+/// `ReplayMeta::is_compressed` is set. Implementations are shared between threads so a single
+/// instance can decode many replays.
+pub trait Decompressor: Send + Sync {
+    fn decompress(&self, uncompressed_size: u64, bytes: &[u8]) -> crate::Result<Vec<u8>>;
+}
+
+/// Calls `OodleLZ_Decompress` from a native Oodle library. The library is loaded and the symbol
+/// resolved once, keep the instance around (e.g. in an `Arc`) to reuse it across replays.
+#[cfg(feature = "oodle-library")]
+pub struct OodleLibrary {
+    path: PathBuf,
+    func: DecompressFunc,
+    // must outlive `func`
+    _library: libloading::Library
+}
+
+#[cfg(feature = "oodle-library")]
+impl OodleLibrary {
+    /// Loads the library at `path`, e.g. a shared object build of Oodle on Linux.
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<OodleLibrary> {
+        let library = libloading::Library::new(path.as_ref())?;
+        let func = unsafe {
+            *library.get::<DecompressFunc>(b"OodleLZ_Decompress")?
+        };
+        Ok(OodleLibrary {
+            path: path.as_ref().to_path_buf(),
+            func,
+            _library: library
+        })
+    }
+
+    /// Loads the Windows DLL from the working directory.
+    pub fn dll() -> crate::Result<OodleLibrary> {
+        Self::load(OODLE_DLL_PATH)
     }
-    if uncompressed_size as i32 != check {
-        // throw an error, work it out later
-        //println!("Compression failure: {} {}", uncompressed_size, check);
-        return Err(crate::ErrorKind::OodleDecodeError.into());
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(feature = "oodle-library")]
+impl Debug for OodleLibrary {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(&format!("oodle library at {}", self.path.display()))
+    }
+}
+
+#[cfg(feature = "oodle-library")]
+impl Decompressor for OodleLibrary {
+    // https://github.com/SirWaddles/JohnWickParse/blob/master/src/decompress/oodle.rs
+    fn decompress(&self, uncompressed_size: u64, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+        let mut output = vec![0u8; uncompressed_size as usize];
+        let check = unsafe {
+            (self.func)(bytes.as_ptr(), bytes.len() as u64, output.as_mut_ptr(), uncompressed_size, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0)
+        };
+        if uncompressed_size as i32 != check {
+            return Err(crate::ErrorKind::OodleDecodeError(format!("decompressed {} of {} bytes", check, uncompressed_size)).into());
+        }
+        Ok(output)
+    }
+}
+
+/// Pass-through for replays whose `ReplayMeta::is_compressed` is false, payloads of those are
+/// never decompressed. Compressed payloads are only accepted if they are stored as is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Uncompressed;
+
+impl Decompressor for Uncompressed {
+    fn decompress(&self, uncompressed_size: u64, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+        if bytes.len() as u64 != uncompressed_size {
+            return Err(crate::ErrorKind::OodleDecodeError(format!("payload of {} bytes is compressed to {}, a decompressor is needed", uncompressed_size, bytes.len())).into());
+        }
+        Ok(bytes.to_vec())
     }
-    Ok(output)
-}
\ No newline at end of file
+}
@@ -2,25 +2,35 @@ pub mod bitreader;
 pub use self::bitreader::BitReader;
 pub mod packet;
 pub mod net;
-mod decompress;
+pub mod replicator;
+pub mod schema;
+pub mod visitor;
+pub mod movement;
+pub mod decompress;
+pub use self::decompress::{Decompressor, Uncompressed};
+#[cfg(feature = "oodle-library")]
+pub use self::decompress::OodleLibrary;
+pub mod oodle;
+pub use self::oodle::OodleDecoder;
 
-use crate::ureplay::Chunk;
-use crate::uchunk::Aes;
-use block_modes::BlockMode;
+use crate::uchunk::decrypt;
+use crate::uetypes::FileVersion;
+use crate::ureplay::ReplayMeta;
 use byteorder::{ReadBytesExt, LE};
 use bincode::ErrorKind;
 use std::io::Read;
-use serde::Deserialize;
 use serde::export::fmt::Debug;
 use serde::export::Formatter;
 
-#[derive(Deserialize, PartialEq)]
+#[derive(PartialEq)]
 pub struct DataChunk {
+    /// Zero before `FileVersion::StreamChunkTimes`.
     pub start: u32,
     pub end: u32,
+    /// Size of the payload, the whole chunk before `FileVersion::StreamChunkTimes`.
     pub length: u32,
+    /// Same as `length` before `FileVersion::Encryption`.
     pub memory_size_in_bytes: u32,
-    #[serde(skip_deserializing)]
     pub data: Vec<u8> // encrypted + compressed, decrypt then decompress (beware of extra bytes to read before decompressing!!!!!!!)
 }
 
@@ -32,23 +42,49 @@ impl Debug for DataChunk {
 
 
 impl DataChunk {
-    pub fn parse(c: Chunk, enc_key: &[u8]) -> crate::Result<DataChunk> {
-        if c.variant != 1 {
-            return Err(crate::ErrorKind::ReplayParseError("Attempting to parse a different type chunk as data chunk".to_string()).into())
+    /// Parses the payload of a data chunk in the layout of the replay's file version, decrypting
+    /// and decompressing the frame data.
+    pub fn from_bytes(data: &[u8], meta: &ReplayMeta, decompressor: &dyn Decompressor) -> crate::Result<DataChunk> {
+        let file_version = meta.file_version;
+        let mut cursor = data;
+        let mut c = DataChunk { start: 0, end: 0, length: 0, memory_size_in_bytes: 0, data: Vec::new() };
+        if file_version >= FileVersion::StreamChunkTimes as u32 {
+            c.start = cursor.read_u32::<LE>()?;
+            c.end = cursor.read_u32::<LE>()?;
+            c.length = cursor.read_u32::<LE>()?;
+        } else {
+            c.length = cursor.len() as u32;
+        }
+        c.memory_size_in_bytes = if file_version >= FileVersion::Encryption as u32 {
+            cursor.read_u32::<LE>()?
+        } else {
+            c.length
+        };
+        if c.length as usize > cursor.len() {
+            return Err(crate::ErrorKind::ReplayParseError(format!("data chunk payload of {} bytes is truncated to {}", c.length, cursor.len())).into());
         }
-        let mut cursor = c.data.as_slice();
-        let mut c: DataChunk = bincode::deserialize_from(&mut cursor)?;
         let mut enc_bytes = vec![0u8; c.length as usize];
-        cursor.read(enc_bytes.as_mut_slice());
-        let cipher = Aes::new_var(enc_key, Default::default())?;
-        let mut dec_bytes_vec = cipher.decrypt_vec(enc_bytes.as_slice())?;
-        let mut dec_bytes = dec_bytes_vec.as_slice();
-        let decompressed_size = dec_bytes.read_i32::<LE>()?;
-        let compressed_size = dec_bytes.read_i32::<LE>()?;
-        let mut compressed_bytes = vec![0u8; compressed_size as usize];
-        dec_bytes.read(compressed_bytes.as_mut_slice());
-        let decompressed = decompress::decompress_stream(decompressed_size as u64, compressed_bytes.as_slice())?;
-        c.data = decompressed;
+        cursor.read_exact(enc_bytes.as_mut_slice())?;
+        c.data = decode_payload(enc_bytes, meta, decompressor)?;
         Ok(c)
     }
+}
+
+/// Decrypts a chunk payload and decompresses it if the replay is compressed, compressed payloads
+/// are prefixed with their decompressed and compressed sizes.
+pub(crate) fn decode_payload(enc_bytes: Vec<u8>, meta: &ReplayMeta, decompressor: &dyn Decompressor) -> crate::Result<Vec<u8>> {
+    let dec_bytes_vec = decrypt(enc_bytes, meta.decryption_key()?)?;
+    if !meta.is_compressed {
+        return Ok(dec_bytes_vec);
+    }
+    let mut dec_bytes = dec_bytes_vec.as_slice();
+    let decompressed_size = dec_bytes.read_i32::<LE>()?;
+    let compressed_size = dec_bytes.read_i32::<LE>()?;
+    if decompressed_size < 0 || compressed_size < 0 || compressed_size as usize > dec_bytes.len() {
+        return Err(crate::ErrorKind::ReplayParseError(format!("invalid compressed sizes {} and {} for a payload of {} bytes",
+                                                              decompressed_size, compressed_size, dec_bytes.len())).into());
+    }
+    let mut compressed_bytes = vec![0u8; compressed_size as usize];
+    dec_bytes.read_exact(compressed_bytes.as_mut_slice())?;
+    decompressor.decompress(decompressed_size as u64, compressed_bytes.as_slice())
 }
\ No newline at end of file
@@ -0,0 +1,133 @@
+use crate::data::net::NetworkGUID;
+use crate::data::packet::{FRotator, FVector};
+use crate::data::replicator::ReplicatedProperties;
+use crate::data::schema::PropertyValue;
+use crate::data::visitor::ReplayVisitor;
+use crate::data::BitReader;
+use crate::uetypes::ChannelCloseReason;
+use std::collections::HashMap;
+
+/// Precision of a quantized vector (`EVectorQuantization`).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VectorQuantization {
+    RoundWholeNumber,
+    RoundOneDecimal,
+    RoundTwoDecimals
+}
+
+impl VectorQuantization {
+    /// Scale factor and maximum bits per component passed to `BitReader::read_packed_vector`.
+    pub fn packing(self) -> (u32, u32) {
+        match self {
+            VectorQuantization::RoundWholeNumber => (1, 24),
+            VectorQuantization::RoundOneDecimal => (10, 27),
+            VectorQuantization::RoundTwoDecimals => (100, 30)
+        }
+    }
+
+    fn read(self, reader: &mut BitReader) -> crate::Result<FVector> {
+        let (scale_factor, max_bits) = self.packing();
+        reader.read_packed_vector(scale_factor, max_bits)
+    }
+}
+
+/// Precision of a compressed rotator (`ERotatorQuantization`).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RotatorQuantization {
+    ByteComponents,
+    ShortComponents
+}
+
+/// Replicated movement of an actor (`FRepMovement`).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RepMovement {
+    pub simulated_physic_sleep: bool,
+    pub rep_physics: bool,
+    pub location: FVector,
+    pub rotation: FRotator,
+    pub linear_velocity: FVector,
+    /// Only sent for actors replicating physics.
+    pub angular_velocity: Option<FVector>
+}
+
+impl RepMovement {
+    pub fn read(reader: &mut BitReader, location: VectorQuantization, rotation: RotatorQuantization,
+                velocity: VectorQuantization) -> crate::Result<RepMovement> {
+        let mut movement = RepMovement {
+            simulated_physic_sleep: reader.read_bit()?,
+            rep_physics: reader.read_bit()?,
+            ..Default::default()
+        };
+        movement.location = location.read(reader)?;
+        movement.rotation = match rotation {
+            RotatorQuantization::ByteComponents => reader.read_rotation_byte()?,
+            RotatorQuantization::ShortComponents => reader.read_rotation_short()?
+        };
+        movement.linear_velocity = velocity.read(reader)?;
+        if movement.rep_physics {
+            movement.angular_velocity = Some(velocity.read(reader)?);
+        }
+        Ok(movement)
+    }
+}
+
+/// Movement of an actor at `time_seconds`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovementSample {
+    pub time_seconds: f32,
+    pub movement: RepMovement
+}
+
+/// Visitor sampling the replicated movement of actors at each frame time, such as the player
+/// pawns decoded by `SchemaRegistry::fortnite`. Actors are sampled from their first movement
+/// update until their channel is closed for a reason other than dormancy.
+///
+/// Timelines are kept per actor, for players that is per pawn: they are not mapped to player
+/// states, and a player whose pawn is replaced (for example after being rebooted) gets a new
+/// timeline under the new pawn's net guid.
+#[derive(Debug, Default)]
+pub struct MovementTimeline {
+    time_seconds: f32,
+    current: HashMap<NetworkGUID, RepMovement>,
+    pub timelines: HashMap<NetworkGUID, Vec<MovementSample>>
+}
+
+impl MovementTimeline {
+    pub fn new() -> MovementTimeline {
+        MovementTimeline::default()
+    }
+
+    /// Samples of `actor` in frame order.
+    pub fn get(&self, actor: &NetworkGUID) -> &[MovementSample] {
+        self.timelines.get(actor).map_or(&[], |x| x.as_slice())
+    }
+}
+
+impl ReplayVisitor for MovementTimeline {
+    fn on_frame(&mut self, time_seconds: f32) {
+        self.time_seconds = time_seconds;
+        for (actor, movement) in &self.current {
+            self.timelines.entry(*actor).or_default().push(MovementSample { time_seconds, movement: movement.clone() });
+        }
+    }
+
+    fn on_property_update(&mut self, properties: &ReplicatedProperties) {
+        for property in &properties.properties {
+            if let PropertyValue::Movement(movement) = &property.value {
+                self.current.insert(properties.actor, movement.clone());
+                let samples = self.timelines.entry(properties.actor).or_default();
+                match samples.last_mut() {
+                    // sampled at the start of this frame, keep the latest movement of the frame
+                    Some(last) if last.time_seconds == self.time_seconds => last.movement = movement.clone(),
+                    _ => samples.push(MovementSample { time_seconds: self.time_seconds, movement: movement.clone() })
+                }
+            }
+        }
+    }
+
+    fn on_channel_closed(&mut self, _channel: u32, actor: Option<NetworkGUID>, _reason: ChannelCloseReason, dormant: bool) {
+        if let (Some(actor), false) = (actor, dormant) {
+            self.current.remove(&actor);
+        }
+    }
+}
@@ -7,9 +7,11 @@ use serde::export::Formatter;
 use crate::data::DataChunk;
 use crate::ErrorKind;
 use std::collections::HashMap;
-use crate::data::packet::PacketParser;
+use crate::data::packet::{FQuat, FTransform, FVector};
+use crate::uchunk::{HeaderChunk, ReplayHeaderFlags};
+use crate::uetypes::NetworkVersion;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct NetFieldExport { //check if exported before deserialization!
     pub handle: u32,
     pub compatible_checksum: u32,
@@ -30,6 +32,29 @@ impl NetFieldExport {
     }
 }
 
+/// Export group stored in checkpoints, mapping a class path to its exported properties.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NetFieldExportGroup {
+    pub path_name: String,
+    pub path_name_index: u32,
+    pub exports: Vec<Option<NetFieldExport>>
+}
+
+impl NetFieldExportGroup {
+    pub fn parse(cursor: &mut &[u8]) -> crate::Result<NetFieldExportGroup> {
+        let mut group = NetFieldExportGroup {
+            path_name: cursor.read_fstring()?,
+            path_name_index: cursor.read_int_packed()?,
+            exports: Vec::new()
+        };
+        let num_exports = cursor.read_int_packed()?;
+        for _ in 0..num_exports {
+            group.exports.push(NetFieldExport::parse(cursor)?);
+        }
+        Ok(group)
+    }
+}
+
 // varint land as we are in networking territory :)
 #[derive(Debug, Default, PartialEq)]
 pub struct NetFieldExports {
@@ -65,13 +90,56 @@ impl NetFieldExports {
     }
 }
 
+/// Replicated data recorded outside of packets for the actor with `net_guid`, `num_bits` of
+/// `payload` are used.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExternalData {
+    pub net_guid: NetworkGUID,
+    pub num_bits: u32,
+    pub payload: Vec<u8>
+}
+
+/// Streaming level that became visible in a frame, levels becoming hidden are not written to
+/// frames. Replays without level streaming fixes also record the package to load and the level
+/// transform.
+#[derive(Debug, Default, PartialEq)]
+pub struct StreamingLevel {
+    pub package_name: String,
+    pub package_name_to_load: Option<String>,
+    pub transform: Option<FTransform>
+}
+
+impl StreamingLevel {
+    fn parse(cursor: &mut &[u8], streaming_fixes: bool) -> crate::Result<StreamingLevel> {
+        let mut level = StreamingLevel {
+            package_name: cursor.read_fstring()?,
+            ..Default::default()
+        };
+        if !streaming_fixes {
+            level.package_name_to_load = Some(cursor.read_fstring()?);
+            let mut floats = [0f32; 10];
+            cursor.read_f32_into::<LE>(&mut floats)?;
+            level.transform = Some(FTransform {
+                rotation: FQuat(floats[0], floats[1], floats[2], floats[3]),
+                translation: FVector(floats[4], floats[5], floats[6]),
+                scale: FVector(floats[7], floats[8], floats[9])
+            });
+        }
+        Ok(level)
+    }
+}
+
+/// Frame of a data chunk, `DemoFrame<&[u8]>` borrows its packets from the chunk.
 #[derive(Debug, Default)]
-pub struct DemoFrame {
+pub struct DemoFrame<P = PlaybackPacket> {
     pub current_level_index: u32,
     pub time_seconds: f32,
     pub export_data: Vec<NetFieldExports>,
-    //pub net_guid_val_to_path: HashMap<u32, String>, todo return in the future
-    pub packets: Vec<PlaybackPacket>
+    pub streaming_levels: Vec<StreamingLevel>,
+    pub external_data: Vec<ExternalData>,
+    /// Net guids exported by the frame with their path names.
+    pub net_guids: Vec<(NetworkGUID, String)>,
+    pub packets: Vec<P>
 }
 
 #[derive(PartialEq)]
@@ -85,11 +153,18 @@ impl Default for PacketState {
     }
 }
 
+#[derive(Default)]
 pub struct PlaybackPacket {
     pub state: PacketState,
     pub data: Vec<u8>
 }
 
+impl AsRef<[u8]> for PlaybackPacket {
+    fn as_ref(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+}
+
 impl Debug for PlaybackPacket {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         f.write_str(&*format!("playback packet with size of {}", self.data.len()))
@@ -175,66 +250,134 @@ impl NetworkGUID {
     }
 }
 
-impl DemoFrame {
-    pub fn parse(cursor: &mut &[u8], packet_parser: &mut PacketParser) -> crate::Result<DemoFrame> {
-        let mut frame = DemoFrame {
-            current_level_index: cursor.read_u32::<LE>()?,
-            time_seconds: cursor.read_f32::<LE>()?,
-            ..Default::default()
-        };
-        frame.export_data = NetFieldExports::parse(cursor)?;
-        let num_guids = cursor.read_int_packed()?;
-        for _ in 0..num_guids {
-            let size = cursor.read_i32::<LE>()?;
-            let mut uobject = vec![0u8; size as usize];
-            cursor.read(uobject.as_mut_slice())?;
-            let o = NetworkGUID::load_internal_object(&mut uobject.as_slice(), true, 0)?;
-            if let Some((key, value)) = o.1 {
-                //frame.net_guid_val_to_path.insert(key, value);
-                packet_parser.net_guid_cache.net_guid_to_path.insert(key, value);
+impl<'a> DemoFrame<&'a [u8]> {
+    /// Parses a frame in the layout given by the header's network version and flags, packets
+    /// are borrowed from `cursor`.
+    pub fn parse_borrowed(cursor: &mut &'a [u8], header: &HeaderChunk) -> crate::Result<DemoFrame<&'a [u8]>> {
+        let streaming_fixes = header.flags.contains(ReplayHeaderFlags::HAS_STREAMING_FIXES);
+        let mut frame = DemoFrame::default();
+        if header.has_network_version(NetworkVersion::MultipleLevels) {
+            frame.current_level_index = cursor.read_u32::<LE>()?;
+        }
+        frame.time_seconds = cursor.read_f32::<LE>()?;
+        if header.has_network_version(NetworkVersion::LevelStreamingFixes) {
+            frame.export_data = NetFieldExports::parse(cursor)?;
+            let num_guids = cursor.read_int_packed()?;
+            for _ in 0..num_guids {
+                let size = cursor.read_i32::<LE>()?;
+                let mut uobject = vec![0u8; size as usize];
+                cursor.read(uobject.as_mut_slice())?;
+                let o = NetworkGUID::load_internal_object(&mut uobject.as_slice(), true, 0)?;
+                if let Some(net_guid) = o.1 {
+                    frame.net_guids.push(net_guid);
+                }
             }
         }
-        let num_streaming_levels = cursor.read_int_packed()?;
+        let num_streaming_levels = if streaming_fixes {
+            cursor.read_int_packed()?
+        } else {
+            cursor.read_i32::<LE>()? as u32
+        };
         for _ in 0..num_streaming_levels {
-            cursor.read_fstring()?; // ok this is so inefficient
+            frame.streaming_levels.push(StreamingLevel::parse(cursor, streaming_fixes)?);
+        }
+        if streaming_fixes {
+            cursor.read_u64::<LE>()?; // external offset
         }
-        cursor.read_u64::<LE>()?; // external offset
         loop {
             let external_data_num_bits = cursor.read_int_packed()?;
             if external_data_num_bits == 0 {
                 break;
             }
-            let net_guid = cursor.read_int_packed()?;
-            let bytes = (external_data_num_bits + 7) >> 3;
-            cursor.read(vec![0u8; bytes as usize].as_mut_slice());
+            let mut data = ExternalData {
+                net_guid: NetworkGUID(cursor.read_int_packed()?),
+                num_bits: external_data_num_bits,
+                payload: vec![0u8; ((external_data_num_bits + 7) >> 3) as usize]
+            };
+            cursor.read_exact(data.payload.as_mut_slice())?;
+            frame.external_data.push(data);
+        }
+        if header.flags.contains(ReplayHeaderFlags::GAME_SPECIFIC_FRAME_DATA) {
+            let game_specific_data_size = cursor.read_u64::<LE>()?;
+            if game_specific_data_size > cursor.len() as u64 {
+                return Err(crate::ErrorKind::ReplayParseError(format!("game specific frame data of {} bytes runs past the frame", game_specific_data_size)).into());
+            }
+            *cursor = cursor.split_at(game_specific_data_size as usize).1;
         }
-        let game_specific_data_size = cursor.read_u64::<LE>()?;
-        cursor.read(vec![0u8; game_specific_data_size as usize].as_mut_slice())?;
         loop {
-            cursor.read_int_packed()?; // seen level index
-            let size = cursor.read_u32::<LE>()?;
-            let mut packet: PlaybackPacket = PlaybackPacket {
-                state: if size == 0 || size < 0 { PacketState::End } else { PacketState::Success },
-                data: vec![]
-            };
-            if let PacketState::Success = packet.state {
-                packet.data = vec![0u8; size as usize];
-                cursor.read(packet.data.as_mut_slice())?;
-            } else {
-                frame.packets.push(packet);
+            if streaming_fixes {
+                cursor.read_int_packed()?; // seen level index
+            }
+            let size = cursor.read_u32::<LE>()? as usize;
+            if size == 0 {
                 break;
             }
-            packet_parser.received_raw_packet(&packet)?;
+            if size > cursor.len() {
+                return Err(ErrorKind::ReplayParseError(format!("packet of {} bytes runs past the end of the frame", size)).into());
+            }
+            let (packet, rest) = cursor.split_at(size);
+            *cursor = rest;
             frame.packets.push(packet);
         }
         Ok(frame)
     }
-    pub fn parse_data(data_chunk: DataChunk, packet_parser: &mut PacketParser) -> crate::Result<Vec<DemoFrame>> {
-        let mut slice = data_chunk.data.as_slice();
-        let mut demo_frames: Vec<DemoFrame> = Vec::new();
-        while !slice.is_empty() {
-            demo_frames.push(Self::parse(&mut slice, packet_parser)?);
+
+    /// Copies the packets out of the chunk, ending them with an `End` packet.
+    pub fn into_owned(self) -> DemoFrame {
+        let mut packets: Vec<PlaybackPacket> = self.packets.into_iter().map(|x| PlaybackPacket {
+            state: PacketState::Success,
+            data: x.to_vec()
+        }).collect();
+        packets.push(PlaybackPacket::default());
+        DemoFrame {
+            current_level_index: self.current_level_index,
+            time_seconds: self.time_seconds,
+            export_data: self.export_data,
+            streaming_levels: self.streaming_levels,
+            external_data: self.external_data,
+            net_guids: self.net_guids,
+            packets
+        }
+    }
+}
+
+impl DemoFrame {
+    /// Parses a frame in the layout given by the header's network version and flags.
+    pub fn parse(cursor: &mut &[u8], header: &HeaderChunk) -> crate::Result<DemoFrame> {
+        Ok(DemoFrame::parse_borrowed(cursor, header)?.into_owned())
+    }
+
+    /// Lazily parses the frames of a data chunk.
+    pub fn frames<'a>(data_chunk: &'a DataChunk, header: &'a HeaderChunk) -> FrameIter<'a> {
+        FrameIter {
+            data: data_chunk.data.as_slice(),
+            header
+        }
+    }
+
+    pub fn parse_data(data_chunk: DataChunk, header: &HeaderChunk) -> crate::Result<Vec<DemoFrame>> {
+        DemoFrame::frames(&data_chunk, header).map(|x| x.map(DemoFrame::into_owned)).collect()
+    }
+}
+
+/// Parses the frames of a data chunk one at a time, packets are borrowed from the chunk.
+pub struct FrameIter<'a> {
+    data: &'a [u8],
+    header: &'a HeaderChunk
+}
+
+impl<'a> Iterator for FrameIter<'a> {
+    type Item = crate::Result<DemoFrame<&'a [u8]>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let frame = DemoFrame::parse_borrowed(&mut self.data, self.header);
+        if frame.is_err() {
+            // stop after the first broken frame
+            self.data = &[];
         }
-        Ok(demo_frames)
+        Some(frame)
     }
 }
\ No newline at end of file
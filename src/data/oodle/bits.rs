@@ -0,0 +1,192 @@
+// Bit readers used by the Oodle stream formats. Reads past the end of a stream yield zero bits,
+// callers validate the final stream positions instead of every read.
+
+/// Reads bits most significant first, either forwards from the start of `buf` or backwards
+/// from its end.
+pub(crate) struct MsbReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    backward: bool
+}
+
+impl<'a> MsbReader<'a> {
+    pub fn forward(buf: &'a [u8]) -> MsbReader<'a> {
+        MsbReader { buf, pos: 0, backward: false }
+    }
+
+    pub fn backward(buf: &'a [u8]) -> MsbReader<'a> {
+        MsbReader { buf, pos: 0, backward: true }
+    }
+
+    #[inline]
+    fn byte(&self, index: usize) -> u64 {
+        if index >= self.buf.len() {
+            return 0;
+        }
+        if self.backward {
+            self.buf[self.buf.len() - 1 - index] as u64
+        } else {
+            self.buf[index] as u64
+        }
+    }
+
+    /// Next 32 bits of the stream without consuming them.
+    #[inline]
+    pub fn peek(&self) -> u32 {
+        let index = self.pos >> 3;
+        let mut value = 0u64;
+        for i in 0..5 {
+            value = (value << 8) | self.byte(index + i);
+        }
+        (value >> (8 - (self.pos & 7))) as u32
+    }
+
+    /// Reads `n` bits, `n` may be zero.
+    #[inline]
+    pub fn read(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        let value = self.peek() >> (32 - n);
+        self.pos += n as usize;
+        value
+    }
+
+    #[inline]
+    pub fn read_bit(&mut self) -> bool {
+        self.read(1) != 0
+    }
+
+    #[inline]
+    pub fn skip(&mut self, n: u32) {
+        self.pos += n as usize;
+    }
+
+    #[inline]
+    pub fn leading_zeros(&self) -> u32 {
+        self.peek().leading_zeros()
+    }
+
+    /// Number of bytes touched so far, including a partially consumed byte.
+    #[inline]
+    pub fn consumed_bytes(&self) -> usize {
+        (self.pos + 7) >> 3
+    }
+
+    #[inline]
+    pub fn overrun(&self) -> bool {
+        self.pos > self.buf.len() * 8
+    }
+
+    /// Reads an LZ match distance whose bit count is parametrized by `v`.
+    pub fn read_distance(&mut self, v: u32) -> u32 {
+        if v < 0xF0 {
+            let n = (v >> 4) + 4;
+            let top = (1 << n) | self.read(n);
+            (top << 4) + (v & 0xF) - 248
+        } else {
+            let n = v - 0xF0 + 4;
+            let top = (1 << n) | self.read(n);
+            8322816 + (top << 12) + self.read(12)
+        }
+    }
+
+    /// Reads a length from the excess length stream.
+    pub fn read_length(&mut self) -> Option<u32> {
+        let n = self.leading_zeros();
+        if n > 12 {
+            return None;
+        }
+        self.skip(n);
+        Some(self.read(n + 7) - 64)
+    }
+
+    /// Reads `n` bits where `n` may exceed 24.
+    pub fn read_more_than_24_bits(&mut self, n: u32) -> u32 {
+        if n <= 24 {
+            self.read(n)
+        } else {
+            let high = self.read(24) << (n - 24);
+            high + self.read(n - 24)
+        }
+    }
+
+    /// Reads a value using truncated binary encoding for the number of symbol range gaps.
+    pub fn read_fluff(&mut self, num_symbols: u32) -> u32 {
+        if num_symbols == 256 {
+            return 0;
+        }
+        let x = std::cmp::min(257 - num_symbols, num_symbols) * 2;
+        let y = 32 - (x - 1).leading_zeros();
+        let v = self.peek() >> (32 - y);
+        let z = (1 << y) - x;
+        if (v >> 1) >= z {
+            self.skip(y);
+            v - z
+        } else {
+            self.skip(y - 1);
+            v >> 1
+        }
+    }
+}
+
+/// Reads bits least significant first, either forwards from the start of `buf` or backwards
+/// from its end.
+pub(crate) struct LsbReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    backward: bool
+}
+
+impl<'a> LsbReader<'a> {
+    pub fn forward(buf: &'a [u8]) -> LsbReader<'a> {
+        LsbReader { buf, pos: 0, backward: false }
+    }
+
+    pub fn backward(buf: &'a [u8]) -> LsbReader<'a> {
+        LsbReader { buf, pos: 0, backward: true }
+    }
+
+    #[inline]
+    fn byte(&self, index: usize) -> u64 {
+        if index >= self.buf.len() {
+            return 0;
+        }
+        if self.backward {
+            self.buf[self.buf.len() - 1 - index] as u64
+        } else {
+            self.buf[index] as u64
+        }
+    }
+
+    /// Next 32 bits of the stream without consuming them, first bit in the lowest position.
+    #[inline]
+    pub fn peek(&self) -> u32 {
+        let index = self.pos >> 3;
+        let mut value = 0u64;
+        for i in 0..5 {
+            value |= self.byte(index + i) << (8 * i);
+        }
+        (value >> (self.pos & 7)) as u32
+    }
+
+    #[inline]
+    pub fn read(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        let value = self.peek() & (u32::MAX >> (32 - n));
+        self.pos += n as usize;
+        value
+    }
+
+    #[inline]
+    pub fn skip(&mut self, n: u32) {
+        self.pos += n as usize;
+    }
+
+    #[inline]
+    pub fn consumed_bytes(&self) -> usize {
+        (self.pos + 7) >> 3
+    }
+}
@@ -0,0 +1,300 @@
+use crate::data::oodle::bits::MsbReader;
+use crate::data::oodle::{corrupt, huffman, tans};
+
+/// Size fields of an entropy coded block.
+struct BlockHeader {
+    chunk_type: u8,
+    header_len: usize,
+    src_size: usize,
+    dst_size: usize
+}
+
+fn block_header(src: &[u8], capacity: usize) -> crate::Result<BlockHeader> {
+    if src.len() < 2 {
+        return corrupt("entropy block header truncated");
+    }
+    let chunk_type = (src[0] >> 4) & 7;
+    let header = if chunk_type == 0 {
+        if src[0] >= 0x80 {
+            let size = ((src[0] as usize) << 8 | src[1] as usize) & 0xFFF;
+            BlockHeader { chunk_type, header_len: 2, src_size: size, dst_size: size }
+        } else {
+            if src.len() < 3 {
+                return corrupt("entropy block header truncated");
+            }
+            let size = (src[0] as usize) << 16 | (src[1] as usize) << 8 | src[2] as usize;
+            if size & !0x3ffff != 0 {
+                return corrupt("stored block too large");
+            }
+            BlockHeader { chunk_type, header_len: 3, src_size: size, dst_size: size }
+        }
+    } else if chunk_type >= 6 {
+        return corrupt(&format!("unknown entropy block type {}", chunk_type));
+    } else if src[0] >= 0x80 {
+        if src.len() < 3 {
+            return corrupt("entropy block header truncated");
+        }
+        let bits = (src[0] as usize) << 16 | (src[1] as usize) << 8 | src[2] as usize;
+        let src_size = bits & 0x3ff;
+        BlockHeader { chunk_type, header_len: 3, src_size, dst_size: src_size + ((bits >> 10) & 0x3ff) + 1 }
+    } else {
+        if src.len() < 5 {
+            return corrupt("entropy block header truncated");
+        }
+        let bits = u32::from_be_bytes([src[1], src[2], src[3], src[4]]) as usize;
+        let src_size = bits & 0x3ffff;
+        let dst_size = (((bits >> 18) | (src[0] as usize) << 14) & 0x3FFFF) + 1;
+        if src_size >= dst_size {
+            return corrupt("entropy block larger than its output");
+        }
+        BlockHeader { chunk_type, header_len: 5, src_size, dst_size }
+    };
+    if header.src_size > src.len() - header.header_len {
+        return corrupt(&format!("entropy block of {} bytes truncated", header.src_size));
+    }
+    if header.dst_size > capacity {
+        return corrupt(&format!("entropy block of {} bytes exceeds capacity {}", header.dst_size, capacity));
+    }
+    Ok(header)
+}
+
+/// Decodes one entropy coded block of at most `capacity` bytes, returns the output and the
+/// number of source bytes consumed.
+pub(crate) fn decode_bytes(src: &[u8], capacity: usize) -> crate::Result<(Vec<u8>, usize)> {
+    let header = block_header(src, capacity)?;
+    let body = &src[header.header_len..header.header_len + header.src_size];
+    let consumed = header.header_len + header.src_size;
+    if header.chunk_type == 0 {
+        return Ok((body.to_vec(), consumed));
+    }
+    let mut output = vec![0u8; header.dst_size];
+    let used = match header.chunk_type {
+        1 => tans::decode(body, &mut output)?,
+        2 | 4 => huffman::decode(body, &mut output, header.chunk_type == 4)?,
+        3 => decode_rle(body, &mut output)?,
+        5 => decode_recursive(body, &mut output)?,
+        _ => unreachable!()
+    };
+    if used != header.src_size {
+        return corrupt(&format!("entropy block used {} of {} bytes", used, header.src_size));
+    }
+    Ok((output, consumed))
+}
+
+fn decode_rle(src: &[u8], output: &mut [u8]) -> crate::Result<usize> {
+    if src.len() <= 1 {
+        if src.len() != 1 {
+            return corrupt("empty rle block");
+        }
+        for x in output.iter_mut() {
+            *x = src[0];
+        }
+        return Ok(1);
+    }
+    let commands = if src[0] != 0 {
+        let (mut commands, used) = decode_bytes(src, 0x3ffff)?;
+        commands.extend_from_slice(&src[used..]);
+        commands
+    } else {
+        src[1..].to_vec()
+    };
+    let mut front = 0usize;
+    let mut back = commands.len();
+    let mut pos = 0usize;
+    let mut rle_byte = 0u8;
+    while front < back {
+        let cmd = commands[back - 1] as u32;
+        let (copy, rle) = if cmd.wrapping_sub(1) >= 0x2f {
+            back -= 1;
+            ((!cmd & 0xF) as usize, (cmd >> 4) as usize)
+        } else if cmd == 1 {
+            back -= 1;
+            if front >= back {
+                return corrupt("rle command truncated");
+            }
+            rle_byte = commands[front];
+            front += 1;
+            continue;
+        } else {
+            if back - front < 2 {
+                return corrupt("rle command truncated");
+            }
+            let data = u16::from_le_bytes([commands[back - 2], commands[back - 1]]) as usize;
+            back -= 2;
+            if cmd >= 0x10 {
+                let data = data - 4096;
+                (data & 0x3F, data >> 6)
+            } else if cmd >= 9 {
+                (0, (data - 0x8ff) * 128)
+            } else {
+                ((data - 511) * 64, 0)
+            }
+        };
+        if output.len() - pos < copy + rle || back.saturating_sub(front) < copy {
+            return corrupt("rle run out of bounds");
+        }
+        output[pos..pos + copy].copy_from_slice(&commands[front..front + copy]);
+        front += copy;
+        pos += copy;
+        for x in output[pos..pos + rle].iter_mut() {
+            *x = rle_byte;
+        }
+        pos += rle;
+    }
+    if front != back || pos != output.len() {
+        return corrupt("rle block did not fill its output");
+    }
+    Ok(src.len())
+}
+
+fn decode_recursive(src: &[u8], output: &mut [u8]) -> crate::Result<usize> {
+    if src.len() < 6 {
+        return corrupt("recursive block too small");
+    }
+    let n = src[0] & 0x7f;
+    if n < 2 {
+        return corrupt("recursive block with fewer than two parts");
+    }
+    if src[0] & 0x80 == 0 {
+        let mut pos = 1;
+        let mut written = 0;
+        for _ in 0..n {
+            let (part, used) = decode_bytes(&src[pos..], output.len() - written)?;
+            output[written..written + part.len()].copy_from_slice(&part);
+            written += part.len();
+            pos += used;
+        }
+        if written != output.len() {
+            return corrupt("recursive block did not fill its output");
+        }
+        Ok(pos)
+    } else {
+        let (data, used) = decode_multi_array(src, output.len())?;
+        if data.len() != output.len() {
+            return corrupt("multi array block did not fill its output");
+        }
+        output.copy_from_slice(&data);
+        Ok(used)
+    }
+}
+
+/// Decodes a multi array block into a single array.
+fn decode_multi_array(src: &[u8], capacity: usize) -> crate::Result<(Vec<u8>, usize)> {
+    if src.len() < 4 {
+        return corrupt("multi array block too small");
+    }
+    if src[0] & 0x80 == 0 {
+        return corrupt("invalid multi array header");
+    }
+    let num_arrays = (src[0] & 0x3f) as usize;
+    let mut pos = 1;
+    if num_arrays == 0 {
+        let (data, used) = decode_bytes(&src[pos..], capacity)?;
+        return Ok((data, pos + used));
+    }
+
+    let mut arrays = Vec::with_capacity(num_arrays);
+    let mut total_size = 0;
+    for _ in 0..num_arrays {
+        let (data, used) = decode_bytes(&src[pos..], 0x3ffff)?;
+        total_size += data.len();
+        arrays.push(data);
+        pos += used;
+    }
+    if src.len() - pos < 3 {
+        return corrupt("multi array block truncated");
+    }
+    let q = u16::from_le_bytes([src[pos], src[pos + 1]]) as usize;
+    pos += 2;
+
+    let num_indexes = block_header(&src[pos..], total_size)?.dst_size;
+    if num_indexes < 2 {
+        return corrupt("multi array block without intervals");
+    }
+    let mut num_lens = num_indexes - 1;
+    let mut indexes;
+    let mut lenlog2;
+    if q & 0x8000 != 0 {
+        let (data, used) = decode_bytes(&src[pos..], num_indexes)?;
+        if data.len() != num_indexes {
+            return corrupt("multi array index count mismatch");
+        }
+        pos += used;
+        indexes = Vec::with_capacity(num_indexes);
+        lenlog2 = Vec::with_capacity(num_indexes);
+        for t in data {
+            lenlog2.push(t >> 4);
+            indexes.push(t & 0xF);
+        }
+        num_lens = num_indexes;
+    } else {
+        let (data, used) = decode_bytes(&src[pos..], num_indexes)?;
+        if data.len() != num_indexes {
+            return corrupt("multi array index count mismatch");
+        }
+        pos += used;
+        indexes = data;
+        let (data, used) = decode_bytes(&src[pos..], num_lens)?;
+        if data.len() != num_lens {
+            return corrupt("multi array length count mismatch");
+        }
+        pos += used;
+        if data.iter().any(|x| *x > 16) {
+            return corrupt("multi array interval too wide");
+        }
+        lenlog2 = data;
+    }
+
+    let varbits_len = q & 0x3FFF;
+    if src.len() - pos < varbits_len {
+        return corrupt("multi array intervals truncated");
+    }
+    let varbits = &src[pos..pos + varbits_len];
+    let mut forward = MsbReader::forward(varbits);
+    let mut backward = MsbReader::backward(varbits);
+    let intervals = lenlog2[..num_lens]
+        .iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let n = *n as u32;
+            let bits = if i % 2 == 0 { &mut forward } else { &mut backward };
+            ((1 << n) | bits.read(n)) as usize
+        })
+        .collect::<Vec<usize>>();
+
+    if indexes[num_indexes - 1] != 0 {
+        return corrupt("multi array intervals not terminated");
+    }
+    let mut offsets = vec![0usize; num_arrays];
+    let mut output = Vec::with_capacity(capacity);
+    let mut index = 0;
+    let mut len_index = 0;
+    loop {
+        let source = indexes[index] as usize;
+        index += 1;
+        if source == 0 {
+            break;
+        }
+        if source > num_arrays || len_index >= num_lens {
+            return corrupt("multi array interval out of range");
+        }
+        let len = intervals[len_index];
+        len_index += 1;
+        let array = &arrays[source - 1];
+        if len > array.len() - offsets[source - 1] || len > capacity - output.len() {
+            return corrupt("multi array interval out of range");
+        }
+        output.extend_from_slice(&array[offsets[source - 1]..offsets[source - 1] + len]);
+        offsets[source - 1] += len;
+    }
+    if q & 0x8000 != 0 {
+        len_index += 1;
+    }
+    if index != num_indexes || len_index != num_lens {
+        return corrupt("multi array intervals left over");
+    }
+    if offsets.iter().zip(arrays.iter()).any(|(offset, array)| *offset != array.len()) {
+        return corrupt("multi array source arrays not fully used");
+    }
+    Ok((output, pos + varbits_len))
+}
@@ -0,0 +1,322 @@
+use crate::data::oodle::bits::{MsbReader, LsbReader};
+use crate::data::oodle::corrupt;
+
+const CODE_PREFIX_ORG: [usize; 12] = [0x0, 0x0, 0x2, 0x6, 0xE, 0x1E, 0x3E, 0x7E, 0xFE, 0x1FE, 0x2FE, 0x3FE];
+
+/// A run of consecutive symbols sharing the code length table.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SymbolRange {
+    pub symbol: u32,
+    pub num: u32
+}
+
+/// Huffman lookup table indexed by the next 11 bits, least significant bit first.
+struct HuffLut {
+    bits2len: [u8; 2048],
+    bits2sym: [u8; 2048]
+}
+
+/// Reads a run of unary coded values (number of zero bits before each set bit).
+pub(crate) fn decode_golomb_rice_lengths(bits: &mut MsbReader, dst: &mut [u8]) -> crate::Result<()> {
+    for value in dst.iter_mut() {
+        let mut count = 0u32;
+        while !bits.read_bit() {
+            count += 1;
+            if bits.overrun() {
+                return corrupt("golomb rice lengths run past the end of the stream");
+            }
+        }
+        *value = count as u8;
+    }
+    Ok(())
+}
+
+/// Appends `bit_count` raw low bits to each value.
+fn decode_golomb_rice_bits(bits: &mut MsbReader, dst: &mut [u8], bit_count: u32) {
+    if bit_count == 0 {
+        return;
+    }
+    for value in dst.iter_mut() {
+        *value = (((*value as u32) << bit_count) | bits.read(bit_count)) as u8;
+    }
+}
+
+/// Converts the gap/run lengths following the code lengths into symbol ranges.
+pub(crate) fn convert_to_ranges(bits: &mut MsbReader, num_symbols: u32, fluff: u32, symlen: &[u8]) -> crate::Result<Vec<SymbolRange>> {
+    let num_ranges = (fluff >> 1) as usize;
+    let mut symlen = symlen.iter();
+    let mut next_len = || symlen.next().copied().unwrap_or(0) as u32;
+    let mut sym_idx = 0u32;
+    if fluff & 1 != 0 {
+        let v = next_len();
+        if v >= 8 {
+            return corrupt("invalid symbol range gap");
+        }
+        sym_idx = bits.read(v + 1) + (1 << (v + 1)) - 1;
+    }
+    let mut ranges = Vec::with_capacity(num_ranges + 1);
+    let mut syms_used = 0u32;
+    for _ in 0..num_ranges {
+        let v = next_len();
+        if v >= 9 {
+            return corrupt("invalid symbol range length");
+        }
+        let num = bits.read(v) + (1 << v);
+        let v = next_len();
+        if v >= 8 {
+            return corrupt("invalid symbol range gap");
+        }
+        let space = bits.read(v + 1) + (1 << (v + 1)) - 1;
+        ranges.push(SymbolRange { symbol: sym_idx, num });
+        syms_used += num;
+        sym_idx += num + space;
+    }
+    if sym_idx >= 256 || syms_used >= num_symbols || sym_idx + num_symbols - syms_used > 256 {
+        return corrupt("symbol ranges out of bounds");
+    }
+    ranges.push(SymbolRange { symbol: sym_idx, num: num_symbols - syms_used });
+    Ok(ranges)
+}
+
+fn push_symbol(syms: &mut [u8], code_prefix: &mut [usize; 12], code_len: usize, symbol: u32) -> crate::Result<()> {
+    let slot = code_prefix[code_len];
+    if code_len + 1 < code_prefix.len() && slot >= CODE_PREFIX_ORG[code_len + 1] || slot >= syms.len() {
+        return corrupt("too many huffman codes of one length");
+    }
+    syms[slot] = symbol as u8;
+    code_prefix[code_len] += 1;
+    Ok(())
+}
+
+fn read_code_lengths_old(bits: &mut MsbReader, syms: &mut [u8], code_prefix: &mut [usize; 12]) -> crate::Result<usize> {
+    if bits.read_bit() {
+        let mut sym = 0i32;
+        let mut num_symbols = 0i32;
+        let mut avg_bits_x4 = 32i32;
+        let forced_bits = bits.read(2);
+        let thres_for_valid_gamma_bits = 1u32 << (31 - (20u32 >> forced_bits));
+        let mut skip_initial_zeros = bits.read_bit();
+        loop {
+            if !skip_initial_zeros {
+                if bits.peek() & 0xff000000 == 0 {
+                    return corrupt("invalid huffman symbol gap");
+                }
+                let lz = bits.leading_zeros();
+                sym += bits.read(2 * (lz + 1)) as i32 - 2 + 1;
+                if sym >= 256 {
+                    break;
+                }
+            }
+            skip_initial_zeros = false;
+            if bits.peek() & 0xff000000 == 0 {
+                return corrupt("invalid huffman symbol run");
+            }
+            let lz = bits.leading_zeros();
+            let mut n = bits.read(2 * (lz + 1)) as i32 - 2 + 1;
+            if sym + n > 256 {
+                return corrupt("huffman symbol run out of bounds");
+            }
+            num_symbols += n;
+            loop {
+                if bits.peek() < thres_for_valid_gamma_bits {
+                    return corrupt("invalid huffman code length");
+                }
+                let lz = bits.leading_zeros() as i32;
+                let v = bits.read(lz as u32 + forced_bits + 1) as i32 + ((lz - 1) << forced_bits);
+                let code_len = (-(v & 1) ^ (v >> 1)) + ((avg_bits_x4 + 2) >> 2);
+                if !(1..=11).contains(&code_len) {
+                    return corrupt("huffman code length out of range");
+                }
+                avg_bits_x4 = code_len + ((3 * avg_bits_x4 + 2) >> 2);
+                push_symbol(syms, code_prefix, code_len as usize, sym as u32)?;
+                sym += 1;
+                n -= 1;
+                if n == 0 {
+                    break;
+                }
+            }
+            if sym == 256 {
+                break;
+            }
+        }
+        if sym != 256 || num_symbols < 2 {
+            return corrupt("huffman code lengths do not cover the alphabet");
+        }
+        Ok(num_symbols as usize)
+    } else {
+        // sparse symbol encoding
+        let num_symbols = bits.read(8);
+        if num_symbols == 0 {
+            return corrupt("huffman table without symbols");
+        }
+        if num_symbols == 1 {
+            syms[0] = bits.read(8) as u8;
+        } else {
+            let code_len_bits = bits.read(3);
+            if code_len_bits > 4 {
+                return corrupt("invalid huffman code length width");
+            }
+            for _ in 0..num_symbols {
+                let sym = bits.read(8);
+                let code_len = bits.read(code_len_bits) + 1;
+                if code_len > 11 {
+                    return corrupt("huffman code length out of range");
+                }
+                push_symbol(syms, code_prefix, code_len as usize, sym)?;
+            }
+        }
+        Ok(num_symbols as usize)
+    }
+}
+
+fn read_code_lengths_new(bits: &mut MsbReader, syms: &mut [u8], code_prefix: &mut [usize; 12]) -> crate::Result<usize> {
+    let forced_bits = bits.read(2);
+    let num_symbols = bits.read(8) + 1;
+    let fluff = bits.read_fluff(num_symbols);
+    let total = (num_symbols + fluff) as usize;
+    let mut code_len = vec![0u8; total];
+    decode_golomb_rice_lengths(bits, &mut code_len)?;
+    decode_golomb_rice_bits(bits, &mut code_len[..num_symbols as usize], forced_bits);
+    let mut running_sum = 0x1eu32;
+    for len in code_len[..num_symbols as usize].iter_mut() {
+        let v = *len as i32;
+        let v = -(v & 1) ^ (v >> 1);
+        let l = v + (running_sum >> 2) as i32 + 1;
+        if !(1..=11).contains(&l) {
+            return corrupt("huffman code length out of range");
+        }
+        *len = l as u8;
+        running_sum = running_sum.wrapping_add(v as u32);
+    }
+    let ranges = convert_to_ranges(bits, num_symbols, fluff, &code_len[num_symbols as usize..])?;
+    let mut lens = code_len.iter();
+    for range in ranges {
+        for sym in range.symbol..range.symbol + range.num {
+            let len = match lens.next() {
+                Some(len) => *len,
+                None => return corrupt("huffman ranges exceed code lengths")
+            };
+            push_symbol(syms, code_prefix, len as usize, sym)?;
+        }
+    }
+    Ok(num_symbols as usize)
+}
+
+fn make_lut(code_prefix: &[usize; 12], syms: &[u8]) -> crate::Result<HuffLut> {
+    let mut len = [0u8; 2048];
+    let mut sym = [0u8; 2048];
+    let mut slot = 0usize;
+    for code_len in 1..12 {
+        let start = CODE_PREFIX_ORG[code_len];
+        let count = code_prefix[code_len] - start;
+        let step = 1usize << (11 - code_len);
+        if slot + (count << (11 - code_len)) > 2048 {
+            return corrupt("oversubscribed huffman code");
+        }
+        for j in 0..count {
+            for k in slot..slot + step {
+                len[k] = code_len as u8;
+                sym[k] = syms[start + j];
+            }
+            slot += step;
+        }
+    }
+    if slot != 2048 {
+        return corrupt("incomplete huffman code");
+    }
+    // streams are read least significant bit first, so index by the reversed code
+    let mut lut = HuffLut { bits2len: [0u8; 2048], bits2sym: [0u8; 2048] };
+    for i in 0..2048usize {
+        let reversed = (i as u32).reverse_bits() as usize >> 21;
+        lut.bits2len[reversed] = len[i];
+        lut.bits2sym[reversed] = sym[i];
+    }
+    Ok(lut)
+}
+
+/// Decodes three interleaved streams: two forwards from the start of `src` and from `split`,
+/// one backwards from the end of `src`.
+fn decode_streams(lut: &HuffLut, src: &[u8], split: usize, output: &mut [u8]) -> crate::Result<()> {
+    if split > src.len() {
+        return corrupt("huffman stream split out of bounds");
+    }
+    let mut first = LsbReader::forward(&src[..split]);
+    let mut mid = LsbReader::forward(&src[split..]);
+    let mut last = LsbReader::backward(&src[split..]);
+    for (i, out) in output.iter_mut().enumerate() {
+        let stream = match i % 3 {
+            0 => &mut first,
+            1 => &mut last,
+            _ => &mut mid
+        };
+        let k = (stream.peek() & 0x7FF) as usize;
+        stream.skip(lut.bits2len[k] as u32);
+        *out = lut.bits2sym[k];
+    }
+    if first.consumed_bytes() != split || mid.consumed_bytes() + last.consumed_bytes() != src.len() - split {
+        return corrupt("huffman streams do not meet");
+    }
+    Ok(())
+}
+
+#[inline]
+fn read_u16(src: &[u8], pos: usize) -> crate::Result<usize> {
+    if pos + 2 > src.len() {
+        return corrupt("huffman header truncated");
+    }
+    Ok(src[pos] as usize | (src[pos + 1] as usize) << 8)
+}
+
+/// Decodes a huffman block, `six_streams` selects the layout with two halves of three streams each.
+pub(crate) fn decode(src: &[u8], output: &mut [u8], six_streams: bool) -> crate::Result<usize> {
+    let mut bits = MsbReader::forward(src);
+    let mut code_prefix = CODE_PREFIX_ORG;
+    let mut syms = [0u8; 1280];
+    let num_syms = if !bits.read_bit() {
+        read_code_lengths_old(&mut bits, &mut syms, &mut code_prefix)?
+    } else if !bits.read_bit() {
+        read_code_lengths_new(&mut bits, &mut syms, &mut code_prefix)?
+    } else {
+        return corrupt("unknown huffman table format");
+    };
+    let mut pos = bits.consumed_bytes();
+    if num_syms == 1 {
+        for x in output.iter_mut() {
+            *x = syms[0];
+        }
+        return Ok(pos);
+    }
+    let lut = make_lut(&code_prefix, &syms)?;
+    if !six_streams {
+        if pos + 3 > src.len() {
+            return corrupt("huffman header truncated");
+        }
+        let split = read_u16(src, pos)?;
+        pos += 2;
+        decode_streams(&lut, &src[pos..], split, output)?;
+    } else {
+        if pos + 6 > src.len() {
+            return corrupt("huffman header truncated");
+        }
+        let half = (output.len() + 1) >> 1;
+        let split_mid = src[pos] as usize | (src[pos + 1] as usize) << 8 | (src[pos + 2] as usize) << 16;
+        pos += 3;
+        if split_mid > src.len() - pos {
+            return corrupt("huffman stream split out of bounds");
+        }
+        let mid = pos + split_mid;
+        let split_left = read_u16(src, pos)?;
+        pos += 2;
+        if mid - pos < split_left + 2 || src.len() - mid < 3 {
+            return corrupt("huffman stream split out of bounds");
+        }
+        let split_right = read_u16(src, mid)?;
+        if src.len() - (mid + 2) < split_right + 2 {
+            return corrupt("huffman stream split out of bounds");
+        }
+        let (left, right) = output.split_at_mut(half);
+        decode_streams(&lut, &src[pos..mid], split_left, left)?;
+        decode_streams(&lut, &src[mid + 2..], split_right, right)?;
+    }
+    Ok(src.len())
+}
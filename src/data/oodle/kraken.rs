@@ -0,0 +1,219 @@
+use crate::data::oodle::bits::MsbReader;
+use crate::data::oodle::corrupt;
+use crate::data::oodle::entropy::decode_bytes;
+
+/// Decoded streams of a Kraken LZ chunk.
+struct LzTable {
+    literals: Vec<u8>,
+    commands: Vec<u8>,
+    offsets: Vec<i32>,
+    lengths: Vec<u32>
+}
+
+fn read_lz_table(src: &[u8], dst_size: usize) -> crate::Result<LzTable> {
+    if src.is_empty() || src[0] & 0x80 != 0 {
+        return corrupt("kraken excess bytes are not supported");
+    }
+    let mut pos = 0;
+    let (literals, used) = decode_bytes(src, dst_size)?;
+    pos += used;
+    let (commands, used) = decode_bytes(&src[pos..], dst_size)?;
+    pos += used;
+    if src.len() - pos < 3 {
+        return corrupt("kraken chunk truncated");
+    }
+
+    let mut scale = 0;
+    let mut low_bits = None;
+    if src[pos] & 0x80 != 0 {
+        scale = src[pos] as i32 - 127;
+        pos += 1;
+    }
+    let (packed_offsets, used) = decode_bytes(&src[pos..], commands.len())?;
+    pos += used;
+    if scale > 1 {
+        let (extra, used) = decode_bytes(&src[pos..], packed_offsets.len())?;
+        if extra.len() != packed_offsets.len() {
+            return corrupt("kraken offset low bits count mismatch");
+        }
+        pos += used;
+        low_bits = Some(extra);
+    }
+    let (packed_lengths, used) = decode_bytes(&src[pos..], dst_size >> 2)?;
+    pos += used;
+
+    let (offsets, lengths) = unpack_offsets(&src[pos..], &packed_offsets, low_bits.as_deref(), scale, &packed_lengths)?;
+    Ok(LzTable { literals, commands, offsets, lengths })
+}
+
+fn unpack_offsets(src: &[u8], packed_offsets: &[u8], low_bits: Option<&[u8]>, scale: i32,
+                  packed_lengths: &[u8]) -> crate::Result<(Vec<i32>, Vec<u32>)> {
+    let mut a = MsbReader::forward(src);
+    let mut b = MsbReader::backward(src);
+    if b.peek() < 0x2000 {
+        return corrupt("invalid kraken length count");
+    }
+    let n = b.leading_zeros();
+    b.skip(n);
+    let long_length_count = b.read(n + 1) - 1;
+    if long_length_count > 512 {
+        return corrupt("too many kraken long lengths");
+    }
+
+    let mut offsets = Vec::with_capacity(packed_offsets.len());
+    for (i, cmd) in packed_offsets.iter().enumerate() {
+        let cmd = *cmd as u32;
+        let bits = if i % 2 == 0 { &mut a } else { &mut b };
+        if scale == 0 {
+            offsets.push(-(bits.read_distance(cmd) as i32));
+        } else {
+            if (cmd >> 3) > 26 {
+                return corrupt("kraken offset too large");
+            }
+            let offset = ((8 + (cmd & 7)) << (cmd >> 3)) | bits.read_more_than_24_bits(cmd >> 3);
+            offsets.push(8 - offset as i32);
+        }
+    }
+    if let Some(low_bits) = low_bits {
+        for (offset, low) in offsets.iter_mut().zip(low_bits.iter()) {
+            *offset = scale.wrapping_mul(*offset).wrapping_sub(*low as i32);
+        }
+    }
+
+    let mut long_lengths = Vec::with_capacity(long_length_count as usize);
+    for i in 0..long_length_count {
+        let bits = if i % 2 == 0 { &mut a } else { &mut b };
+        match bits.read_length() {
+            Some(length) => long_lengths.push(length),
+            None => return corrupt("invalid kraken long length")
+        }
+    }
+    if a.consumed_bytes() + b.consumed_bytes() != src.len() {
+        return corrupt("kraken offset streams do not meet");
+    }
+
+    let mut long_lengths = long_lengths.into_iter();
+    let mut lengths = Vec::with_capacity(packed_lengths.len());
+    for v in packed_lengths {
+        let v = if *v == 255 {
+            match long_lengths.next() {
+                Some(length) => length + 255,
+                None => return corrupt("kraken long lengths exhausted")
+            }
+        } else {
+            *v as u32
+        };
+        lengths.push(v + 3);
+    }
+    if long_lengths.next().is_some() {
+        return corrupt("kraken long lengths left over");
+    }
+    Ok((offsets, lengths))
+}
+
+fn next_length(lengths: &mut std::slice::Iter<u32>) -> crate::Result<usize> {
+    match lengths.next() {
+        Some(length) => Ok(*length as usize),
+        None => corrupt("kraken length stream exhausted")
+    }
+}
+
+/// Copies literals, adding the byte at `last_offset` when using delta literals.
+fn copy_literals(output: &mut [u8], pos: usize, literals: &[u8], last_offset: i32, delta: bool) -> crate::Result<()> {
+    if delta && (pos as i64 + last_offset as i64) < 0 {
+        return corrupt("kraken delta literal out of bounds");
+    }
+    for (i, literal) in literals.iter().enumerate() {
+        output[pos + i] = if delta {
+            literal.wrapping_add(output[(pos + i).wrapping_sub(-last_offset as usize)])
+        } else {
+            *literal
+        };
+    }
+    Ok(())
+}
+
+fn process_lz_runs(table: &LzTable, output: &mut [u8], start: usize, end: usize, delta: bool) -> crate::Result<()> {
+    let mut pos = start;
+    let mut literals = table.literals.as_slice();
+    let mut offsets = table.offsets.iter();
+    let mut lengths = table.lengths.iter();
+    let mut recent = [-8i32; 7];
+    let mut last_offset = -8i32;
+
+    for f in table.commands.iter() {
+        let f = *f as usize;
+        let mut litlen = f & 3;
+        if litlen == 3 {
+            litlen = next_length(&mut lengths)?;
+        }
+        if litlen > literals.len() || litlen > end - pos {
+            return corrupt("kraken literal run out of bounds");
+        }
+        copy_literals(output, pos, &literals[..litlen], last_offset, delta)?;
+        pos += litlen;
+        literals = &literals[litlen..];
+
+        let index = f >> 6;
+        if index == 3 {
+            recent[6] = match offsets.next() {
+                Some(offset) => *offset,
+                None => return corrupt("kraken offset stream exhausted")
+            };
+        }
+        let offset = recent[index + 3];
+        for k in (1..=index).rev() {
+            recent[k + 3] = recent[k + 2];
+        }
+        recent[3] = offset;
+        last_offset = offset;
+        if offset >= 0 || -offset as usize > pos {
+            return corrupt(&format!("kraken match offset {} out of bounds at {}", offset, pos));
+        }
+
+        let matchlen = (f >> 2) & 0xF;
+        let matchlen = if matchlen != 15 {
+            matchlen + 2
+        } else {
+            14 + next_length(&mut lengths)?
+        };
+        if matchlen > end - pos {
+            return corrupt("kraken match length out of bounds");
+        }
+        let from = pos - (-offset as usize);
+        for i in 0..matchlen {
+            output[pos + i] = output[from + i];
+        }
+        pos += matchlen;
+    }
+
+    if offsets.next().is_some() || lengths.next().is_some() {
+        return corrupt("kraken streams left over");
+    }
+    if end - pos != literals.len() {
+        return corrupt("kraken literal count mismatch");
+    }
+    copy_literals(output, pos, literals, last_offset, delta)
+}
+
+/// Decodes an LZ chunk into `output[start..end]`. The first chunk of a stream begins with 8 raw bytes.
+pub(crate) fn decode_lz(mode: u32, src: &[u8], output: &mut [u8], start: usize, end: usize) -> crate::Result<()> {
+    if mode > 1 {
+        return corrupt(&format!("unknown kraken mode {}", mode));
+    }
+    if src.len() < 13 {
+        return corrupt("kraken chunk too small");
+    }
+    let mut src = src;
+    let mut start_pos = start;
+    if start == 0 {
+        if end < 8 {
+            return corrupt("kraken chunk too small");
+        }
+        output[..8].copy_from_slice(&src[..8]);
+        src = &src[8..];
+        start_pos = 8;
+    }
+    let table = read_lz_table(src, end - start)?;
+    process_lz_runs(&table, output, start_pos, end, mode == 0)
+}
@@ -0,0 +1,267 @@
+use crate::data::oodle::corrupt;
+use crate::data::oodle::entropy::decode_bytes;
+
+/// Decoded streams of a Mermaid LZ chunk, the length stream is left in the source.
+struct LzTable {
+    literals: Vec<u8>,
+    commands: Vec<u8>,
+    second_commands: usize,
+    off16: Vec<u16>,
+    off32: [Vec<u32>; 2],
+    length_start: usize
+}
+
+fn read_u16(src: &[u8], pos: usize) -> crate::Result<usize> {
+    if src.len() < pos + 2 {
+        return corrupt("mermaid chunk truncated");
+    }
+    Ok(u16::from_le_bytes([src[pos], src[pos + 1]]) as usize)
+}
+
+/// Reads `count` 24 bit offsets relative to `offset`, extended to 30 bits beyond 12 MiB.
+fn decode_far_offsets(src: &[u8], count: usize, offset: usize) -> crate::Result<(Vec<u32>, usize)> {
+    let mut pos = 0;
+    let mut offsets = Vec::with_capacity(count);
+    for _ in 0..count {
+        if src.len() - pos < 3 {
+            return corrupt("mermaid far offsets truncated");
+        }
+        let mut off = src[pos] as usize | (src[pos + 1] as usize) << 8 | (src[pos + 2] as usize) << 16;
+        pos += 3;
+        if offset >= 0xC00000 - 1 && off >= 0xc00000 {
+            if pos == src.len() {
+                return corrupt("mermaid far offsets truncated");
+            }
+            off += (src[pos] as usize) << 22;
+            pos += 1;
+        }
+        if off > offset {
+            return corrupt(&format!("mermaid far offset {} out of bounds at {}", off, offset));
+        }
+        offsets.push(off as u32);
+    }
+    Ok((offsets, pos))
+}
+
+fn read_lz_table(src: &[u8], dst_size: usize, offset: usize) -> crate::Result<LzTable> {
+    let mut pos = 0;
+    let (literals, used) = decode_bytes(src, dst_size)?;
+    pos += used;
+    let (commands, used) = decode_bytes(&src[pos..], dst_size)?;
+    pos += used;
+
+    let second_commands = if dst_size <= 0x10000 {
+        commands.len()
+    } else {
+        let split = read_u16(src, pos)?;
+        pos += 2;
+        if split > commands.len() {
+            return corrupt("mermaid command split out of bounds");
+        }
+        split
+    };
+
+    let off16_count = read_u16(src, pos)?;
+    pos += 2;
+    let off16 = if off16_count == 0xffff {
+        let (high, used) = decode_bytes(&src[pos..], dst_size >> 1)?;
+        pos += used;
+        let (low, used) = decode_bytes(&src[pos..], dst_size >> 1)?;
+        pos += used;
+        if low.len() != high.len() {
+            return corrupt("mermaid offset halves differ in size");
+        }
+        low.iter().zip(high.iter()).map(|(lo, hi)| *lo as u16 + *hi as u16 * 256).collect()
+    } else {
+        let mut off16 = Vec::with_capacity(off16_count);
+        for _ in 0..off16_count {
+            off16.push(read_u16(src, pos)? as u16);
+            pos += 2;
+        }
+        off16
+    };
+
+    if src.len() - pos < 3 {
+        return corrupt("mermaid chunk truncated");
+    }
+    let tmp = src[pos] as usize | (src[pos + 1] as usize) << 8 | (src[pos + 2] as usize) << 16;
+    pos += 3;
+    let mut off32 = [Vec::new(), Vec::new()];
+    if tmp != 0 {
+        let mut size1 = tmp >> 12;
+        let mut size2 = tmp & 0xFFF;
+        if size1 == 4095 {
+            size1 = read_u16(src, pos)?;
+            pos += 2;
+        }
+        if size2 == 4095 {
+            size2 = read_u16(src, pos)?;
+            pos += 2;
+        }
+        let (offsets, used) = decode_far_offsets(&src[pos..], size1, offset)?;
+        off32[0] = offsets;
+        pos += used;
+        let (offsets, used) = decode_far_offsets(&src[pos..], size2, offset + 0x10000)?;
+        off32[1] = offsets;
+        pos += used;
+    }
+    Ok(LzTable { literals, commands, second_commands, off16, off32, length_start: pos })
+}
+
+/// Cursor over the decoded streams shared by both halves of a chunk.
+struct Streams<'a> {
+    src: &'a [u8],
+    lengths: usize,
+    literals: &'a [u8],
+    off16: std::slice::Iter<'a, u16>,
+    recent: isize,
+    delta: bool
+}
+
+impl<'a> Streams<'a> {
+    fn read_length(&mut self) -> crate::Result<usize> {
+        if self.lengths >= self.src.len() {
+            return corrupt("mermaid length stream exhausted");
+        }
+        let mut length = self.src[self.lengths] as usize;
+        if length > 251 {
+            if self.src.len() - self.lengths < 3 {
+                return corrupt("mermaid length stream exhausted");
+            }
+            length += u16::from_le_bytes([self.src[self.lengths + 1], self.src[self.lengths + 2]]) as usize * 4;
+            self.lengths += 2;
+        }
+        self.lengths += 1;
+        Ok(length)
+    }
+
+    fn next_off16(&mut self) -> crate::Result<usize> {
+        match self.off16.next() {
+            Some(offset) => Ok(*offset as usize),
+            None => corrupt("mermaid near offset stream exhausted")
+        }
+    }
+
+    fn copy_literals(&mut self, output: &mut [u8], pos: usize, count: usize) -> crate::Result<()> {
+        let recent = self.recent;
+        if count > self.literals.len() || (self.delta && (pos as isize + recent) < 0) {
+            return corrupt("mermaid literal run out of bounds");
+        }
+        for i in 0..count {
+            output[pos + i] = if self.delta {
+                self.literals[i].wrapping_add(output[(pos as isize + i as isize + recent) as usize])
+            } else {
+                self.literals[i]
+            };
+        }
+        self.literals = &self.literals[count..];
+        Ok(())
+    }
+
+    /// Runs the commands of one 64 KiB half, far offsets are relative to `begin`.
+    fn process(&mut self, commands: &[u8], off32: &[u32], output: &mut [u8], begin: usize, start: usize,
+               end: usize) -> crate::Result<()> {
+        let mut pos = start;
+        let mut off32 = off32.iter();
+        let mut next_off32 = || match off32.next() {
+            Some(offset) => Ok(*offset as isize),
+            None => corrupt("mermaid far offset stream exhausted")
+        };
+        for cmd in commands {
+            let cmd = *cmd as usize;
+            if cmd >= 24 {
+                let litlen = cmd & 7;
+                if litlen > end - pos {
+                    return corrupt("mermaid literal run out of bounds");
+                }
+                self.copy_literals(output, pos, litlen)?;
+                pos += litlen;
+                if cmd & 0x80 == 0 {
+                    self.recent = -(self.next_off16()? as isize);
+                }
+                let len = (cmd >> 3) & 0xF;
+                copy_match(output, pos, pos as isize + self.recent, len, end)?;
+                pos += len;
+            } else if cmd > 2 {
+                let len = cmd + 5;
+                let from = begin as isize - next_off32()?;
+                self.recent = from - pos as isize;
+                copy_match(output, pos, from, len, end)?;
+                pos += len;
+            } else if cmd == 0 {
+                let len = self.read_length()? + 64;
+                if len > end - pos {
+                    return corrupt("mermaid literal run out of bounds");
+                }
+                self.copy_literals(output, pos, len)?;
+                pos += len;
+            } else if cmd == 1 {
+                let len = self.read_length()? + 91;
+                let from = pos as isize - self.next_off16()? as isize;
+                self.recent = from - pos as isize;
+                copy_match(output, pos, from, len, end)?;
+                pos += len;
+            } else {
+                let len = self.read_length()? + 29;
+                let from = begin as isize - next_off32()?;
+                self.recent = from - pos as isize;
+                copy_match(output, pos, from, len, end)?;
+                pos += len;
+            }
+        }
+        self.copy_literals(output, pos, end - pos)
+    }
+}
+
+fn copy_match(output: &mut [u8], pos: usize, from: isize, len: usize, end: usize) -> crate::Result<()> {
+    if from < 0 || from as usize >= pos || len > end - pos {
+        return corrupt(&format!("mermaid match out of bounds at {}", pos));
+    }
+    let from = from as usize;
+    for i in 0..len {
+        output[pos + i] = output[from + i];
+    }
+    Ok(())
+}
+
+/// Decodes an LZ chunk into `output[start..end]`. The first chunk of a stream begins with 8 raw bytes.
+pub(crate) fn decode_lz(mode: u32, src: &[u8], output: &mut [u8], start: usize, end: usize) -> crate::Result<()> {
+    if mode > 1 {
+        return corrupt(&format!("unknown mermaid mode {}", mode));
+    }
+    if src.len() < 10 {
+        return corrupt("mermaid chunk too small");
+    }
+    let mut src = src;
+    if start == 0 {
+        if end < 8 {
+            return corrupt("mermaid chunk too small");
+        }
+        output[..8].copy_from_slice(&src[..8]);
+        src = &src[8..];
+    }
+    let table = read_lz_table(src, end - start, start)?;
+    let mut streams = Streams {
+        src,
+        lengths: table.length_start,
+        literals: &table.literals,
+        off16: table.off16.iter(),
+        recent: -8,
+        delta: mode == 0
+    };
+    let halves = [&table.commands[..table.second_commands], &table.commands[table.second_commands..]];
+    let mut begin = start;
+    for (i, commands) in halves.iter().enumerate() {
+        if begin == end {
+            break;
+        }
+        let half_end = std::cmp::min(begin + 0x10000, end);
+        let first = if begin == 0 { 8 } else { begin };
+        streams.process(commands, &table.off32[i], output, begin, first, half_end)?;
+        begin = half_end;
+    }
+    if streams.lengths != src.len() {
+        return corrupt("mermaid length stream not fully used");
+    }
+    Ok(())
+}
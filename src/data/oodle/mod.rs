@@ -0,0 +1,152 @@
+// Pure Rust decoder for the Oodle Kraken and Mermaid (also emitted by Selkie) formats used to
+// compress replay data chunks, based on the reverse engineered ooz decoder.
+
+use crate::data::decompress::Decompressor;
+
+mod bits;
+mod entropy;
+mod huffman;
+mod kraken;
+mod mermaid;
+mod tans;
+
+const KRAKEN: u8 = 6;
+const MERMAID: u8 = 10;
+
+pub(crate) fn corrupt<T>(msg: &str) -> crate::Result<T> {
+    Err(crate::ErrorKind::OodleDecodeError(msg.to_string()).into())
+}
+
+type LzDecoder = fn(u32, &[u8], &mut [u8], usize, usize) -> crate::Result<()>;
+
+/// Decodes one quantum of at most 256 KiB into `output[start..end]`, returns the number of source
+/// bytes consumed.
+fn decode_quantum(lz: LzDecoder, src: &[u8], output: &mut [u8], start: usize, end: usize) -> crate::Result<usize> {
+    let mut src_pos = 0;
+    let mut pos = start;
+    while pos != end {
+        let dst_count = std::cmp::min(end - pos, 0x20000);
+        if src.len() - src_pos < 4 {
+            return corrupt(&format!("chunk header truncated at {}", pos));
+        }
+        let chunk_header = (src[src_pos] as usize) << 16 | (src[src_pos + 1] as usize) << 8 | src[src_pos + 2] as usize;
+        let src_used = if chunk_header & 0x800000 == 0 {
+            let (data, used) = entropy::decode_bytes(&src[src_pos..], dst_count)?;
+            if data.len() != dst_count {
+                return corrupt(&format!("entropy chunk of {} bytes, expected {}", data.len(), dst_count));
+            }
+            output[pos..pos + dst_count].copy_from_slice(&data);
+            used
+        } else {
+            src_pos += 3;
+            let src_used = chunk_header & 0x7FFFF;
+            let mode = ((chunk_header >> 19) & 0xF) as u32;
+            if src.len() - src_pos < src_used {
+                return corrupt(&format!("chunk of {} bytes truncated at {}", src_used, pos));
+            }
+            if src_used < dst_count {
+                lz(mode, &src[src_pos..src_pos + src_used], output, pos, pos + dst_count)?;
+            } else if src_used > dst_count || mode != 0 {
+                return corrupt(&format!("invalid stored chunk at {}", pos));
+            } else {
+                output[pos..pos + dst_count].copy_from_slice(&src[src_pos..src_pos + dst_count]);
+            }
+            src_used
+        };
+        src_pos += src_used;
+        pos += dst_count;
+    }
+    Ok(src_pos)
+}
+
+/// Decompresses an Oodle stream into exactly `size` bytes.
+pub fn decompress(src: &[u8], size: usize) -> crate::Result<Vec<u8>> {
+    let mut output = vec![0u8; size];
+    let mut src_pos = 0;
+    let mut pos = 0;
+    let mut decoder_type = 0;
+    let mut uncompressed = false;
+    let mut use_checksums = false;
+    while pos < size {
+        if pos & 0x3FFFF == 0 {
+            if src.len() - src_pos < 2 {
+                return corrupt(&format!("block header truncated at {}", pos));
+            }
+            let b = src[src_pos];
+            if b & 0xF != 0xC || (b >> 4) & 3 != 0 {
+                return corrupt(&format!("invalid block header at {}", pos));
+            }
+            uncompressed = (b >> 6) & 1 != 0;
+            decoder_type = src[src_pos + 1] & 0x7F;
+            use_checksums = src[src_pos + 1] >> 7 != 0;
+            if decoder_type != KRAKEN && decoder_type != MERMAID {
+                return corrupt(&format!("unsupported decoder type {}", decoder_type));
+            }
+            src_pos += 2;
+        }
+        let dst_left = std::cmp::min(0x40000, size - pos);
+        if uncompressed {
+            if src.len() - src_pos < dst_left {
+                return corrupt(&format!("stored block of {} bytes truncated at {}", dst_left, pos));
+            }
+            output[pos..pos + dst_left].copy_from_slice(&src[src_pos..src_pos + dst_left]);
+            src_pos += dst_left;
+            pos += dst_left;
+            continue;
+        }
+
+        if src.len() - src_pos < 3 {
+            return corrupt(&format!("quantum header truncated at {}", pos));
+        }
+        let v = (src[src_pos] as usize) << 16 | (src[src_pos + 1] as usize) << 8 | src[src_pos + 2] as usize;
+        if v & 0x3FFFF == 0x3FFFF {
+            if v >> 18 != 1 || src.len() - src_pos < 4 {
+                return corrupt(&format!("invalid quantum header at {}", pos));
+            }
+            let value = src[src_pos + 3];
+            for x in output[pos..pos + dst_left].iter_mut() {
+                *x = value;
+            }
+            src_pos += 4;
+            pos += dst_left;
+            continue;
+        }
+        let compressed_size = (v & 0x3FFFF) + 1;
+        // the quantum checksum is not verified
+        src_pos += if use_checksums { 6 } else { 3 };
+        if src_pos > src.len() || compressed_size > src.len() - src_pos {
+            return corrupt(&format!("quantum of {} bytes truncated at {}", compressed_size, pos));
+        }
+        if compressed_size > dst_left {
+            return corrupt(&format!("quantum of {} bytes larger than its output at {}", compressed_size, pos));
+        }
+        let quantum = &src[src_pos..src_pos + compressed_size];
+        if compressed_size == dst_left {
+            output[pos..pos + dst_left].copy_from_slice(quantum);
+        } else {
+            let lz: LzDecoder = if decoder_type == KRAKEN { kraken::decode_lz } else { mermaid::decode_lz };
+            let used = decode_quantum(lz, quantum, &mut output, pos, pos + dst_left)?;
+            if used != compressed_size {
+                return corrupt(&format!("quantum used {} of {} bytes at {}", used, compressed_size, pos));
+            }
+        }
+        src_pos += compressed_size;
+        pos += dst_left;
+    }
+    if src_pos != src.len() {
+        return corrupt(&format!("{} trailing bytes after {} decoded bytes", src.len() - src_pos, size));
+    }
+    Ok(output)
+}
+
+/// Decodes Oodle compressed chunks without the native library, used by `FNSkim::skim` for
+/// compressed replays. Tested against Kraken, Mermaid and Selkie output of the Oodle 2.7 library
+/// shipped with the game, Leviathan is not supported.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OodleDecoder;
+
+impl Decompressor for OodleDecoder {
+    fn decompress(&self, uncompressed_size: u64, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+        decompress(bytes, uncompressed_size as usize)
+    }
+}
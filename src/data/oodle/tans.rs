@@ -0,0 +1,240 @@
+use crate::data::oodle::bits::{MsbReader, LsbReader};
+use crate::data::oodle::corrupt;
+use crate::data::oodle::huffman::{convert_to_ranges, decode_golomb_rice_lengths};
+
+/// Symbols with weight one and (symbol << 16 | weight) pairs for the remaining symbols.
+#[derive(Default)]
+struct TansData {
+    a: Vec<u8>,
+    b: Vec<u32>
+}
+
+#[derive(Clone, Copy, Default)]
+struct TansLutEnt {
+    x: u32,
+    bits_x: u8,
+    symbol: u8,
+    w: u16
+}
+
+fn decode_table(bits: &mut MsbReader, l_bits: u32) -> crate::Result<TansData> {
+    let mut data = TansData::default();
+    let l = 1u32 << l_bits;
+    if bits.read_bit() {
+        let q = bits.read(3);
+        let num_symbols = bits.read(8) + 1;
+        if num_symbols < 2 {
+            return corrupt("tans table with a single symbol");
+        }
+        let fluff = bits.read_fluff(num_symbols);
+        let mut rice = vec![0u8; (num_symbols + fluff) as usize];
+        decode_golomb_rice_lengths(bits, &mut rice)?;
+        let ranges = convert_to_ranges(bits, num_symbols, fluff, &rice[num_symbols as usize..])?;
+        let mut average = 6i32;
+        let mut weight_sum = 0u32;
+        let mut rice = rice.iter();
+        for range in ranges {
+            for symbol in range.symbol..range.symbol + range.num {
+                let nextra = q + *rice.next().unwrap_or(&0) as u32;
+                if nextra > 15 {
+                    return corrupt("tans weight out of range");
+                }
+                let mut v = (bits.read(nextra) + (1 << nextra) - (1 << q)) as i32;
+                let average_div4 = average >> 2;
+                let mut limit = 2 * average_div4;
+                if v <= limit {
+                    v = average_div4 + (-(v & 1) ^ ((v as u32) >> 1) as i32);
+                }
+                if limit > v {
+                    limit = v;
+                }
+                v += 1;
+                average += limit - average_div4;
+                if v == 1 {
+                    data.a.push(symbol as u8);
+                } else {
+                    data.b.push((symbol << 16) + v as u32);
+                }
+                weight_sum = weight_sum.wrapping_add(v as u32);
+            }
+        }
+        if weight_sum != l {
+            return corrupt("tans weights do not sum to the table size");
+        }
+    } else {
+        let mut seen = [false; 256];
+        let mut count = bits.read(3) + 1;
+        let bits_per_sym = 32 - l_bits.leading_zeros();
+        let max_delta_bits = bits.read(bits_per_sym);
+        if max_delta_bits == 0 || max_delta_bits > l_bits {
+            return corrupt("invalid tans delta width");
+        }
+        let mut weight = 0u32;
+        let mut total_weights = 0u32;
+        while count > 0 {
+            let sym = bits.read(8);
+            if seen[sym as usize] {
+                return corrupt("duplicate tans symbol");
+            }
+            weight += bits.read(max_delta_bits);
+            if weight == 0 {
+                return corrupt("zero tans weight");
+            }
+            seen[sym as usize] = true;
+            if weight == 1 {
+                data.a.push(sym as u8);
+            } else {
+                data.b.push((sym << 16) + weight);
+            }
+            total_weights += weight;
+            count -= 1;
+        }
+        let sym = bits.read(8);
+        if seen[sym as usize] {
+            return corrupt("duplicate tans symbol");
+        }
+        if total_weights >= l || l - total_weights < weight || l - total_weights <= 1 {
+            return corrupt("tans weights exceed the table size");
+        }
+        data.b.push((sym << 16) + (l - total_weights));
+        data.a.sort_unstable();
+        data.b.sort_unstable();
+    }
+    Ok(data)
+}
+
+fn init_lut(data: &TansData, l_bits: u32) -> crate::Result<Vec<TansLutEnt>> {
+    let l = 1usize << l_bits;
+    let mut lut = vec![TansLutEnt::default(); l];
+    let a_used = data.a.len();
+    if a_used > l {
+        return corrupt("tans table overflow");
+    }
+    let slots_left = l - a_used;
+    let sa = slots_left >> 2;
+    let mut pointers = [0usize; 4];
+    let mut sb = sa + ((slots_left & 3) > 0) as usize;
+    pointers[1] = sb;
+    sb += sa + ((slots_left & 3) > 1) as usize;
+    pointers[2] = sb;
+    sb += sa + ((slots_left & 3) > 2) as usize;
+    pointers[3] = sb;
+
+    for (i, symbol) in data.a.iter().enumerate() {
+        lut[slots_left + i] = TansLutEnt { x: (1 << l_bits) - 1, bits_x: l_bits as u8, symbol: *symbol, w: 0 };
+    }
+
+    let mut put = |pointer: &mut usize, entry: TansLutEnt| -> crate::Result<()> {
+        if *pointer >= slots_left {
+            return corrupt("tans table overflow");
+        }
+        lut[*pointer] = entry;
+        *pointer += 1;
+        Ok(())
+    };
+
+    let mut weights_sum = 0u32;
+    for b in data.b.iter() {
+        let weight = b & 0xffff;
+        let symbol = (b >> 16) as u8;
+        if weight > 4 {
+            let sym_bits = 31 - weight.leading_zeros();
+            let mut z = l_bits - sym_bits;
+            let mut le = TansLutEnt {
+                x: (1 << z) - 1,
+                bits_x: z as u8,
+                symbol,
+                w: ((l as u32 - 1) & (weight << z)) as u16
+            };
+            let mut what_to_add = 1u32 << z;
+            let mut x = (1u32 << (sym_bits + 1)) - weight;
+            for (j, pointer) in pointers.iter_mut().enumerate() {
+                let y = (weight + (weights_sum.wrapping_sub(j as u32 + 1) & 3)) >> 2;
+                if x >= y {
+                    for _ in 0..y {
+                        put(pointer, le)?;
+                        le.w = (le.w as u32 + what_to_add) as u16;
+                    }
+                    x -= y;
+                } else {
+                    for _ in 0..x {
+                        put(pointer, le)?;
+                        le.w = (le.w as u32 + what_to_add) as u16;
+                    }
+                    z -= 1;
+                    what_to_add >>= 1;
+                    le.bits_x = z as u8;
+                    le.w = 0;
+                    le.x >>= 1;
+                    for _ in 0..y - x {
+                        put(pointer, le)?;
+                        le.w = (le.w as u32 + what_to_add) as u16;
+                    }
+                    x = weight;
+                }
+            }
+        } else if weight > 0 {
+            let mut bits = ((1u32 << weight) - 1) << (weights_sum & 3);
+            bits |= bits >> 4;
+            for ww in weight..weight * 2 {
+                let idx = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let weight_bits = 31 - ww.leading_zeros();
+                put(&mut pointers[idx & 3], TansLutEnt {
+                    x: (1 << (l_bits - weight_bits)) - 1,
+                    bits_x: (l_bits - weight_bits) as u8,
+                    symbol,
+                    w: ((l as u32 - 1) & (ww << (l_bits - weight_bits))) as u16
+                })?;
+            }
+        }
+        weights_sum += weight;
+    }
+    Ok(lut)
+}
+
+/// Decodes a tANS block of `output.len()` bytes.
+pub(crate) fn decode(src: &[u8], output: &mut [u8]) -> crate::Result<usize> {
+    if src.len() < 8 || output.len() < 5 {
+        return corrupt("tans block too small");
+    }
+    let mut bits = MsbReader::forward(src);
+    if bits.read_bit() {
+        return corrupt("tans reserved bit set");
+    }
+    let l_bits = bits.read(2) + 8;
+    let table = decode_table(&mut bits, l_bits)?;
+    let start = bits.consumed_bytes();
+    if start + 8 > src.len() {
+        return corrupt("tans streams truncated");
+    }
+    let lut = init_lut(&table, l_bits)?;
+    let streams = &src[start..];
+    let mut forward = LsbReader::forward(streams);
+    let mut backward = LsbReader::backward(streams);
+    let mut states = [0u32; 5];
+    states[0] = forward.read(l_bits);
+    states[1] = backward.read(l_bits);
+    states[2] = forward.read(l_bits);
+    states[3] = backward.read(l_bits);
+    states[4] = forward.read(l_bits);
+
+    let body_len = output.len() - 5;
+    for (i, out) in output[..body_len].iter_mut().enumerate() {
+        let state = &mut states[i % 5];
+        let stream = if (i / 5) % 2 == 0 { &mut forward } else { &mut backward };
+        let e = lut[*state as usize];
+        *out = e.symbol;
+        *state = (stream.read(e.bits_x as u32) & e.x) + e.w as u32;
+    }
+    if forward.consumed_bytes() + backward.consumed_bytes() != streams.len() {
+        return corrupt("tans streams do not meet");
+    }
+    for (out, state) in output[body_len..].iter_mut().zip(states.iter()) {
+        if *state > 0xFF {
+            return corrupt("invalid final tans state");
+        }
+        *out = *state as u8;
+    }
+    Ok(src.len())
+}
@@ -1,4 +1,4 @@
-use crate::data::net::{PlaybackPacket, NetworkGUID};
+use crate::data::net::{PlaybackPacket, NetworkGUID, ExternalData, DemoFrame};
 use crate::ErrorKind;
 use std::io::Read;
 use crate::uetypes::{ChannelName, ChannelCloseReason, UEReadExt, UnrealName};
@@ -6,6 +6,12 @@ use byteorder::{ReadBytesExt, LE};
 use crate::strum::AsStaticRef;
 use crate::data::BitReader;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt::{Debug, Formatter};
+use crate::uchunk::{CheckpointChunk, CheckpointState, HeaderChunk};
+use crate::data::replicator::{self, NetFieldExportGroupMap, ReplicatedProperties, Rpc};
+use crate::data::schema::SchemaRegistry;
+use crate::data::visitor::ReplayVisitor;
 
 #[derive(Default, Debug, Clone)]
 struct DataBunch {
@@ -47,25 +53,120 @@ pub struct PacketParser {
     in_reliable: i32, // 0
     channels: Vec<Option<UChannel>>, //32767
     partial_bunch: Option<DataBunch>,
-    pub net_guid_cache: NetGuidCache
+    external_data: Option<HashMap<NetworkGUID, Vec<ExternalData>>>,
+    replication: bool,
+    replicated: Vec<ReplicatedProperties>,
+    channel_events: Vec<ChannelEvent>,
+    rpcs: Vec<Rpc>,
+    visitors: Vec<Box<dyn ReplayVisitor>>,
+    time_seconds: f32,
+    pub net_guid_cache: NetGuidCache,
+    pub actors: ActorRegistry,
+    pub export_groups: NetFieldExportGroupMap,
+    /// Types of the replicated properties, Fortnite schemas by default.
+    pub schemas: SchemaRegistry
 }
 
 // x, y, z
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct FVector(pub f32, pub f32, pub f32);
 // pitch, yaw, roll
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct FRotator(pub f32, pub f32, pub f32);
+// x, y, z, w
+#[derive(Debug, Default, PartialEq)]
+pub struct FQuat(pub f32, pub f32, pub f32, pub f32);
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FTransform {
+    pub rotation: FQuat,
+    pub translation: FVector,
+    pub scale: FVector
+}
 
+/// Lifecycle of an actor channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelEvent {
+    /// Channel `index` was opened for `actor`.
+    Opened { index: u32, actor: NetworkGUID },
+    /// Channel `index` was closed, `dormant` channels keep their actor in the world while
+    /// other reasons mean it was destroyed, torn off or stopped being relevant.
+    Closed { index: u32, actor: Option<NetworkGUID>, reason: ChannelCloseReason, dormant: bool }
+}
+
+/// Actor as sent when its channel opened, the transform is only sent for dynamic actors.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Actor {
+    pub actor_net_guid: NetworkGUID,
+    pub archetype: NetworkGUID,
+    pub level: NetworkGUID,
+    pub location: FVector,
+    pub rotation: FRotator,
+    pub scale: FVector,
+    pub velocity: FVector
+}
+
+/// Actor kept by the `ActorRegistry`, `archetype_path` is the path of its archetype or of the
+/// actor itself for static actors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnedActor {
+    pub actor: Actor,
+    pub archetype_path: Option<String>,
+    pub channel: u32,
+    pub spawn_time: f32,
+    pub despawn_time: Option<f32>
+}
+
+/// Every actor spawned so far. Actors spawned again, such as dormant actors, keep their first
+/// spawn time and pick up an archetype path resolved since, closing a channel for dormancy does
+/// not despawn its actor.
 #[derive(Debug, Default)]
-struct Actor {
-    actor_net_guid: NetworkGUID,
-    archetype: NetworkGUID,
-    level: NetworkGUID,
-    location: FVector,
-    rotation: FRotator,
-    scale: FVector,
-    velocity: FVector
+pub struct ActorRegistry {
+    pub actors: HashMap<NetworkGUID, SpawnedActor>
+}
+
+impl ActorRegistry {
+    fn spawned(&mut self, actor: SpawnedActor) -> &SpawnedActor {
+        match self.actors.entry(actor.actor.actor_net_guid) {
+            Entry::Occupied(entry) => {
+                let entry = entry.into_mut();
+                entry.channel = actor.channel;
+                entry.despawn_time = None;
+                if actor.archetype_path.is_some() {
+                    entry.archetype_path = actor.archetype_path;
+                }
+                entry
+            }
+            Entry::Vacant(entry) => entry.insert(actor)
+        }
+    }
+
+    fn despawned(&mut self, net_guid: NetworkGUID, time_seconds: f32) {
+        if let Some(actor) = self.actors.get_mut(&net_guid) {
+            actor.despawn_time = Some(time_seconds);
+        }
+    }
+
+    pub fn get(&self, net_guid: &NetworkGUID) -> Option<&SpawnedActor> {
+        self.actors.get(net_guid)
+    }
+
+    /// Actors with the archetype path `class_name`, e.g. `PlayerPawn_Athena_C`.
+    pub fn of_class<'a>(&'a self, class_name: &'a str) -> impl Iterator<Item = &'a SpawnedActor> + 'a {
+        self.actors.values().filter(move |x| x.archetype_path.as_deref() == Some(class_name))
+    }
+}
+
+impl Debug for PacketParser {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(&format!("packet parser with {} actors", self.actors.actors.len()))
+    }
+}
+
+impl Default for PacketParser {
+    fn default() -> Self {
+        PacketParser::new()
+    }
 }
 
 impl PacketParser {
@@ -79,20 +180,106 @@ impl PacketParser {
             in_reliable: 0,
             channels: vec,
             partial_bunch: Option::None,
+            external_data: Option::None,
+            replication: true,
+            replicated: Vec::new(),
+            channel_events: Vec::new(),
+            rpcs: Vec::new(),
+            visitors: Vec::new(),
+            time_seconds: 0.0,
             net_guid_cache: NetGuidCache::default(),
+            actors: ActorRegistry::default(),
+            export_groups: NetFieldExportGroupMap::default(),
+            schemas: SchemaRegistry::fortnite(),
+        }
+    }
+
+    /// Creates a parser with the state saved in `checkpoint`, so parsing can start at its data chunk.
+    pub fn from_checkpoint(checkpoint: &CheckpointChunk, header: &HeaderChunk) -> crate::Result<(PacketParser, CheckpointState)> {
+        let mut parser = PacketParser::new();
+        let state = checkpoint.read_state(header, &mut parser)?;
+        Ok((parser, state))
+    }
+
+    /// Whether content blocks of actor bunches are read, otherwise only channels and actors are
+    /// tracked. Enabled by default.
+    pub fn set_replication(&mut self, enabled: bool) {
+        self.replication = enabled;
+    }
+
+    /// Registers a visitor called for everything parsed from now on, in registration order.
+    pub fn add_visitor(&mut self, visitor: Box<dyn ReplayVisitor>) {
+        self.visitors.push(visitor);
+    }
+
+    /// Processes a parsed frame: caches its net guids and export groups, collects its external
+    /// data and parses its packets.
+    pub fn received_frame<P: AsRef<[u8]>>(&mut self, frame: &DemoFrame<P>) -> crate::Result<()> {
+        self.time_seconds = frame.time_seconds;
+        for visitor in &mut self.visitors {
+            visitor.on_frame(frame.time_seconds);
+        }
+        for (guid, path) in &frame.net_guids {
+            self.received_net_guid(*guid, path.clone());
+        }
+        self.export_groups.received_exports(&frame.export_data);
+        for data in &frame.external_data {
+            self.received_external_data(data);
+        }
+        for packet in &frame.packets {
+            if !packet.as_ref().is_empty() {
+                self.received_packet_data(packet.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the properties replicated since the last call.
+    pub fn take_replicated(&mut self) -> Vec<ReplicatedProperties> {
+        std::mem::take(&mut self.replicated)
+    }
+
+    /// Takes the functions called since the last call.
+    pub fn take_rpcs(&mut self) -> Vec<Rpc> {
+        std::mem::take(&mut self.rpcs)
+    }
+
+    /// Takes the channels opened and closed since the last call.
+    pub fn take_channel_events(&mut self) -> Vec<ChannelEvent> {
+        std::mem::take(&mut self.channel_events)
+    }
+
+    /// Keeps external data of the following frames, grouped by the net guid of its actor.
+    pub fn collect_external_data(&mut self) {
+        self.external_data.get_or_insert_with(HashMap::new);
+    }
+
+    /// External data collected for `net_guid` since `collect_external_data` was called.
+    pub fn external_data(&self, net_guid: &NetworkGUID) -> &[ExternalData] {
+        self.external_data.as_ref().and_then(|x| x.get(net_guid)).map_or(&[], |x| x.as_slice())
+    }
+
+    pub fn received_external_data(&mut self, data: &ExternalData) {
+        if let Some(external_data) = self.external_data.as_mut() {
+            external_data.entry(data.net_guid).or_insert_with(Vec::new).push(data.clone());
         }
     }
 
     //#[inline]
     pub fn received_raw_packet(&mut self, packet: &PlaybackPacket) -> crate::Result<()> {
-        let mut last_byte = packet.data[packet.data.len() - 1];
+        self.received_packet_data(packet.data.as_slice())
+    }
+
+    /// Processes the payload of a playback packet.
+    pub fn received_packet_data(&mut self, data: &[u8]) -> crate::Result<()> {
+        let mut last_byte = *data.last().ok_or_else(|| ErrorKind::ReplayParseError("empty packet".to_string()))?;
         if last_byte != 0 {
-            let mut bit_size = (packet.data.len() * 8) - 1;
+            let mut bit_size = (data.len() * 8) - 1;
             while !((last_byte & 0x80) >= 1) {
                 last_byte *= 2;
                 bit_size -= 1;
             }
-            self.received_packet(BitReader::new(&mut packet.data.as_slice(), bit_size))?;
+            self.received_packet(BitReader::new(&mut &data[..], bit_size))?;
             return Ok(())
         }
         Err(ErrorKind::ReplayParseError("malformed packet".to_string()).into())
@@ -230,11 +417,18 @@ impl PacketParser {
                                      load_object_recursion_counter: i32) -> crate::Result<NetworkGUID> {
         let (guid, cache_entry) = NetworkGUID::load_internal_object(cursor, is_exporting_net_guid_bunch, load_object_recursion_counter)?;
         if let Some((guid, path)) = cache_entry {
-            self.net_guid_cache.net_guid_to_path.insert(guid, path);
+            self.received_net_guid(guid, path);
         }
         Ok(guid)
     }
 
+    fn received_net_guid(&mut self, guid: NetworkGUID, path: String) {
+        for visitor in &mut self.visitors {
+            visitor.on_net_guid_exported(guid, &path);
+        }
+        self.net_guid_cache.net_guid_to_path.insert(guid, path);
+    }
+
     fn read_content_block_header(&mut self,
                                  bunch: &DataBunch,
                                  bit_reader: &mut BitReader,
@@ -305,11 +499,25 @@ impl PacketParser {
                     reader.read_byte()?;
                 }
             }
-            //todo channel open
+            let path_guid = if in_actor.archetype.is_valid() { in_actor.archetype } else { in_actor.actor_net_guid };
+            let spawned = self.actors.spawned(SpawnedActor {
+                actor: in_actor.clone(),
+                archetype_path: self.net_guid_cache.net_guid_to_path.get(&path_guid).cloned(),
+                channel: bunch.ch_index,
+                spawn_time: self.time_seconds,
+                despawn_time: None
+            });
+            for visitor in &mut self.visitors {
+                visitor.on_actor_spawned(spawned);
+            }
+            self.channel_events.push(ChannelEvent::Opened { index: bunch.ch_index, actor: in_actor.actor_net_guid });
             self.channels[bunch.ch_index as usize].as_mut().expect("???").actor = Some(in_actor); // weird rust semantics, if we borrowed this as a mutable reference initially, load object would fail to compile
         }
         //todo
         //unimplemented!();
+        if !self.replication {
+            return Ok(())
+        }
         while !reader.at_end() {
             let mut b_object_deleted = false;
             let mut b_out_has_rep_layout = false;
@@ -321,7 +529,54 @@ impl PacketParser {
             if rep_object == 0 || bits_size <= 0 {
                 continue; //continue todo
             }
-            //todo receive replicator bunch
+            self.received_replicator_bunch(bunch, rep_object, b_out_has_rep_layout, bit_vec, bits_size)?;
+        }
+        Ok(())
+    }
+
+    /// Maps the payload of a content block through the export groups of the class of
+    /// `rep_object`: payloads with a rep layout start with properties, followed by function calls
+    /// described by the `ClassNetCache` group of the class.
+    fn received_replicator_bunch(&mut self, bunch: &DataBunch, rep_object: u32, b_has_rep_layout: bool,
+                                 bit_vec: Vec<u8>, bits_size: u32) -> crate::Result<()> {
+        let class_name = match self.net_guid_cache.net_guid_to_path.get(&NetworkGUID(rep_object)) {
+            Some(class_name) => class_name,
+            None => return Ok(())
+        };
+        let actor = self.channels[bunch.ch_index as usize].as_ref().and_then(|x| x.actor.as_ref()).map_or(NetworkGUID::default(), |x| x.actor_net_guid);
+        let mut slice = bit_vec.as_slice();
+        let mut reader = BitReader::new(&mut slice, bits_size as usize);
+        if b_has_rep_layout {
+            let group = match self.export_groups.for_class(class_name) {
+                Some(group) => group,
+                None => return Ok(()) // class without exported properties
+            };
+            let properties = replicator::read_properties(&mut reader, group, &self.schemas)?;
+            if !properties.is_empty() {
+                let replicated = ReplicatedProperties {
+                    channel: bunch.ch_index,
+                    actor,
+                    path_name: group.path_name.clone(),
+                    properties
+                };
+                for visitor in &mut self.visitors {
+                    visitor.on_property_update(&replicated);
+                }
+                self.replicated.push(replicated);
+            }
+            if reader.at_end() {
+                return Ok(())
+            }
+        }
+        let class_net_cache = match self.export_groups.for_class(&format!("{}_ClassNetCache", class_name)) {
+            Some(group) => group,
+            None => return Ok(())
+        };
+        for rpc in replicator::read_rpcs(&mut reader, bunch.ch_index, actor, class_net_cache, &self.export_groups, &self.schemas)? {
+            for visitor in &mut self.visitors {
+                visitor.on_rpc(&rpc);
+            }
+            self.rpcs.push(rpc);
         }
         Ok(())
     }
@@ -342,8 +597,19 @@ impl PacketParser {
     fn received_sequenced_bunch(&mut self, bunch: DataBunch) -> crate::Result<bool> {
         self.received_actor_bunch(&bunch);
         if bunch.b_close {
-            self.channels[bunch.ch_index as usize] = None;
-            //todo channelclose thing
+            let actor = self.channels[bunch.ch_index as usize].take().and_then(|x| x.actor).map(|x| x.actor_net_guid);
+            if let (Some(actor), false) = (actor, bunch.b_dormant) {
+                self.actors.despawned(actor, self.time_seconds);
+            }
+            for visitor in &mut self.visitors {
+                visitor.on_channel_closed(bunch.ch_index, actor, bunch.close_reason, bunch.b_dormant);
+            }
+            self.channel_events.push(ChannelEvent::Closed {
+                index: bunch.ch_index,
+                actor,
+                reason: bunch.close_reason,
+                dormant: bunch.b_dormant
+            });
             return Ok(true)
         }
         Ok(false)
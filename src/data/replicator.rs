@@ -0,0 +1,197 @@
+use crate::data::net::{NetFieldExport, NetFieldExportGroup, NetFieldExports, NetworkGUID, StringExt};
+use crate::data::BitReader;
+use crate::data::schema::{PropertyValue, SchemaRegistry};
+use crate::uetypes::UEReadExt;
+use crate::ErrorKind;
+use std::collections::HashMap;
+
+/// Export groups received so far, keyed by path name. Groups persist across frames, later
+/// frames only send the exports that changed.
+#[derive(Debug, Default)]
+pub struct NetFieldExportGroupMap {
+    groups: HashMap<String, NetFieldExportGroup>,
+    path_names: HashMap<u32, String>,
+    classes: HashMap<String, String>,
+    functions: HashMap<String, String>
+}
+
+/// Replicated property of an actor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyUpdate {
+    pub handle: u32,
+    pub name: String,
+    pub value: PropertyValue
+}
+
+/// Properties replicated for an object of the actor on `channel`, `path_name` names the export
+/// group they belong to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicatedProperties {
+    pub channel: u32,
+    pub actor: NetworkGUID,
+    pub path_name: String,
+    pub properties: Vec<PropertyUpdate>
+}
+
+/// Function called on the actor on `channel`, with the parameters that were sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rpc {
+    pub channel: u32,
+    pub actor: NetworkGUID,
+    pub function_name: String,
+    pub params: Vec<PropertyUpdate>
+}
+
+impl NetFieldExportGroupMap {
+    /// Adds a complete group, replacing any group with the same path name.
+    pub fn add_group(&mut self, group: NetFieldExportGroup) {
+        self.path_names.insert(group.path_name_index, group.path_name.clone());
+        let class_name = group.path_name.clone().remove_all_path_prefixes();
+        if let Some((_, function_name)) = class_name.split_once(':') {
+            self.functions.insert(function_name.to_string(), group.path_name.clone());
+        }
+        self.classes.insert(class_name, group.path_name.clone());
+        self.groups.insert(group.path_name.clone(), group);
+    }
+
+    /// Applies the export data of a frame, creating the groups it exports and filling in their
+    /// properties.
+    pub fn received_exports(&mut self, exports: &[NetFieldExports]) {
+        for x in exports {
+            if let (true, Some(path_name)) = (x.is_exported, &x.path_name) {
+                let mut group = NetFieldExportGroup {
+                    path_name: path_name.clone(),
+                    path_name_index: x.path_name_index,
+                    exports: Vec::new()
+                };
+                group.exports.resize_with(x.num_exports.unwrap_or(0) as usize, || None);
+                self.add_group(group);
+            }
+            let export = match &x.export {
+                Some(export) => export,
+                None => continue
+            };
+            let groups = &mut self.groups;
+            let group = match self.path_names.get(&x.path_name_index).and_then(|x| groups.get_mut(x)) {
+                Some(group) => group,
+                None => continue
+            };
+            let handle = export.handle as usize;
+            if handle >= group.exports.len() {
+                group.exports.resize_with(handle + 1, || None);
+            }
+            group.exports[handle] = Some(export.clone());
+        }
+    }
+
+    pub fn get(&self, path_name: &str) -> Option<&NetFieldExportGroup> {
+        self.groups.get(path_name)
+    }
+
+    pub fn by_index(&self, path_name_index: u32) -> Option<&NetFieldExportGroup> {
+        self.path_names.get(&path_name_index).and_then(|x| self.groups.get(x))
+    }
+
+    /// Group of a class, `class_name` is a path with its prefixes removed as kept by the net
+    /// guid cache.
+    pub fn for_class(&self, class_name: &str) -> Option<&NetFieldExportGroup> {
+        self.classes.get(class_name).and_then(|x| self.groups.get(x))
+    }
+
+    /// Parameter group of a function of `class_name`, function groups are named `Class:Function`
+    /// after the class declaring the function. Functions inherited from a parent class are found
+    /// by their name alone.
+    pub fn for_function(&self, class_name: &str, function_name: &str) -> Option<&NetFieldExportGroup> {
+        self.for_class(&format!("{}:{}", class_name, function_name))
+            .or_else(|| self.functions.get(function_name).and_then(|x| self.groups.get(x)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+/// Field of a field stream, `num_bits` of `data` hold its payload.
+struct Field<'a> {
+    handle: u32,
+    name: &'a str,
+    data: Vec<u8>,
+    num_bits: u32
+}
+
+/// Reads the handles and payloads of a field stream, fields that have not been exported are
+/// skipped. Rep layout handles are int packed and offset by one with 0 ending the stream, while
+/// `ClassNetCache` handles are serialized ints bounded by the number of exports.
+fn read_fields<'a>(reader: &mut BitReader, group: &'a NetFieldExportGroup, rep_layout: bool) -> crate::Result<Vec<Field<'a>>> {
+    let mut fields = Vec::new();
+    while !reader.at_end() {
+        let handle = if rep_layout {
+            match reader.read_int_packed()? {
+                0 => break,
+                handle => handle - 1
+            }
+        } else {
+            reader.read_serialized_int(std::cmp::max(group.exports.len() as u32, 2))?
+        };
+        let mut num_bits = reader.read_int_packed()?;
+        if num_bits as usize > reader.remaining_len() {
+            return Err(ErrorKind::ReplayParseError(format!("property {} of {} runs past the payload", handle, group.path_name)).into());
+        }
+        let size = num_bits;
+        let data = reader.read_bits(&mut num_bits)?;
+        if let Some(Some(NetFieldExport { name, .. })) = group.exports.get(handle as usize) {
+            fields.push(Field { handle, name: name.as_str(), data, num_bits: size });
+        }
+    }
+    Ok(fields)
+}
+
+/// Reads the property handles and payloads of a replicator payload, decoding them with the
+/// schemas of the group. The payload starts with a checksum bit, properties that have not been
+/// exported are skipped.
+pub fn read_properties(reader: &mut BitReader, group: &NetFieldExportGroup, schemas: &SchemaRegistry) -> crate::Result<Vec<PropertyUpdate>> {
+    reader.read_bit()?; // do checksum
+    decode_properties(reader, group, schemas)
+}
+
+/// Reads properties without the leading checksum bit, as sent for function parameters.
+fn decode_properties(reader: &mut BitReader, group: &NetFieldExportGroup, schemas: &SchemaRegistry) -> crate::Result<Vec<PropertyUpdate>> {
+    let class_name = group.path_name.clone().remove_all_path_prefixes();
+    Ok(read_fields(reader, group, true)?.into_iter().map(|x| PropertyUpdate {
+        handle: x.handle,
+        name: x.name.to_string(),
+        value: schemas.decode(&class_name, x.name, x.data, x.num_bits)
+    }).collect())
+}
+
+/// Reads the function calls on the actor on `channel` through the `ClassNetCache` group of its
+/// class, parameters are decoded with the `Class:Function` export group of each function. Fields
+/// that are not functions, such as custom delta properties, are skipped.
+pub fn read_rpcs(reader: &mut BitReader, channel: u32, actor: NetworkGUID, class_net_cache: &NetFieldExportGroup,
+                 groups: &NetFieldExportGroupMap, schemas: &SchemaRegistry) -> crate::Result<Vec<Rpc>> {
+    let cache_name = class_net_cache.path_name.clone().remove_all_path_prefixes();
+    let class_name = cache_name.strip_suffix("_ClassNetCache").unwrap_or(&cache_name);
+    let mut rpcs = Vec::new();
+    for field in read_fields(reader, class_net_cache, false)? {
+        let params = match groups.for_function(class_name, field.name) {
+            Some(function) => {
+                let mut slice = field.data.as_slice();
+                let mut params_reader = BitReader::new(&mut slice, field.num_bits as usize);
+                decode_properties(&mut params_reader, function, schemas)?
+            }
+            None if field.num_bits == 0 => Vec::new(),
+            None => continue
+        };
+        rpcs.push(Rpc {
+            channel,
+            actor,
+            function_name: field.name.to_string(),
+            params
+        });
+    }
+    Ok(rpcs)
+}
@@ -0,0 +1,148 @@
+use crate::data::movement::{RepMovement, RotatorQuantization, VectorQuantization};
+use crate::data::net::NetworkGUID;
+use crate::data::packet::{FRotator, FVector};
+use crate::data::BitReader;
+use crate::uetypes::UEReadExt;
+use byteorder::{ReadBytesExt, LE};
+use std::collections::HashMap;
+
+/// How a replicated property is serialized.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyType {
+    IntPacked,
+    UInt32,
+    Int32,
+    Float,
+    Bool,
+    /// Full precision vector.
+    Vector,
+    /// Vector quantized with `scale_factor` in at most `max_bits` bits per component.
+    QuantizedVector { scale_factor: u32, max_bits: u32 },
+    /// Rotator compressed to shorts.
+    Rotator,
+    Name,
+    String,
+    /// Reference to an object by its net guid.
+    Object,
+    /// Dynamic array, only changed elements are sent.
+    Array(Box<PropertyType>),
+    /// `FRepMovement` with the quantization of its location, rotation and velocities.
+    RepMovement { location: VectorQuantization, rotation: RotatorQuantization, velocity: VectorQuantization }
+}
+
+/// Decoded value of a replicated property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Int(u32),
+    Int32(i32),
+    Float(f32),
+    Bool(bool),
+    Vector(FVector),
+    Rotator(FRotator),
+    Name(String),
+    String(String),
+    Object(NetworkGUID),
+    /// Array of `len` elements with the changed elements and their indices.
+    Array { len: u32, elements: Vec<(u32, PropertyValue)> },
+    Movement(RepMovement),
+    /// Property without a known type or not matching its type, `num_bits` of `data` are used.
+    Raw { num_bits: u32, data: Vec<u8> }
+}
+
+impl PropertyType {
+    /// Decodes a property payload, falling back to `PropertyValue::Raw` if it does not hold
+    /// exactly one value of this type.
+    pub fn decode(&self, data: Vec<u8>, num_bits: u32) -> PropertyValue {
+        let mut slice = data.as_slice();
+        let mut reader = BitReader::new(&mut slice, num_bits as usize);
+        match self.read(&mut reader) {
+            Ok(value) if reader.pos() == num_bits as usize => value,
+            _ => PropertyValue::Raw { num_bits, data }
+        }
+    }
+
+    fn read(&self, reader: &mut BitReader) -> crate::Result<PropertyValue> {
+        Ok(match self {
+            PropertyType::IntPacked => PropertyValue::Int(reader.read_int_packed()?),
+            PropertyType::UInt32 => PropertyValue::Int(reader.read_u32::<LE>()?),
+            PropertyType::Int32 => PropertyValue::Int32(reader.read_i32::<LE>()?),
+            PropertyType::Float => PropertyValue::Float(reader.read_f32::<LE>()?),
+            PropertyType::Bool => PropertyValue::Bool(reader.read_bit()?),
+            PropertyType::Vector => PropertyValue::Vector(reader.read_vector()?),
+            PropertyType::QuantizedVector { scale_factor, max_bits } => PropertyValue::Vector(reader.read_packed_vector(*scale_factor, *max_bits)?),
+            PropertyType::Rotator => PropertyValue::Rotator(reader.read_rotation_short()?),
+            PropertyType::Name => PropertyValue::Name(reader.read_bit_fname()?),
+            PropertyType::String => PropertyValue::String(reader.read_fstring()?),
+            PropertyType::Object => PropertyValue::Object(NetworkGUID::load_internal_object(reader, false, 0)?.0),
+            PropertyType::Array(inner) => {
+                let len = reader.read_int_packed()?;
+                let mut elements = Vec::new();
+                loop {
+                    let index = reader.read_int_packed()?;
+                    if index == 0 {
+                        break;
+                    }
+                    loop {
+                        let handle = reader.read_int_packed()?;
+                        if handle == 0 {
+                            break;
+                        }
+                        let mut num_bits = reader.read_int_packed()?;
+                        let size = num_bits;
+                        let data = reader.read_bits(&mut num_bits)?;
+                        elements.push((index - 1, inner.decode(data, size)));
+                    }
+                }
+                PropertyValue::Array { len, elements }
+            }
+            PropertyType::RepMovement { location, rotation, velocity } => PropertyValue::Movement(RepMovement::read(reader, *location, *rotation, *velocity)?)
+        })
+    }
+}
+
+/// Property types by export group and property name, groups are named by their path with the
+/// prefixes removed (e.g. `FortPlayerStateAthena`).
+#[derive(Debug, Default, Clone)]
+pub struct SchemaRegistry {
+    classes: HashMap<String, HashMap<String, PropertyType>>
+}
+
+impl SchemaRegistry {
+    pub fn new() -> SchemaRegistry {
+        SchemaRegistry::default()
+    }
+
+    /// Schemas of common Fortnite properties.
+    pub fn fortnite() -> SchemaRegistry {
+        let mut schemas = SchemaRegistry::new();
+        schemas.register("FortPlayerStateAthena", "PlayerNamePrivate", PropertyType::String);
+        schemas.register("FortPlayerStateAthena", "bIsABot", PropertyType::Bool);
+        schemas.register("FortPlayerStateAthena", "KillScore", PropertyType::UInt32);
+        schemas.register("FortPlayerStateAthena", "TeamKillScore", PropertyType::UInt32);
+        schemas.register("FortPlayerStateAthena", "Place", PropertyType::Int32);
+        schemas.register("FortPickupAthena", "PickupTarget", PropertyType::Object);
+        schemas.register("FortPickupAthena", "bPickedUp", PropertyType::Bool);
+        schemas.register("PlayerPawn_Athena_C", "ReplicatedMovement", PropertyType::RepMovement {
+            location: VectorQuantization::RoundTwoDecimals,
+            rotation: RotatorQuantization::ShortComponents,
+            velocity: VectorQuantization::RoundWholeNumber
+        });
+        schemas
+    }
+
+    pub fn register(&mut self, class_name: &str, property: &str, property_type: PropertyType) {
+        self.classes.entry(class_name.to_string()).or_default().insert(property.to_string(), property_type);
+    }
+
+    pub fn get(&self, class_name: &str, property: &str) -> Option<&PropertyType> {
+        self.classes.get(class_name).and_then(|x| x.get(property))
+    }
+
+    /// Decodes a property of `class_name`, unknown properties are kept as raw bits.
+    pub fn decode(&self, class_name: &str, property: &str, data: Vec<u8>, num_bits: u32) -> PropertyValue {
+        match self.get(class_name, property) {
+            Some(property_type) => property_type.decode(data, num_bits),
+            None => PropertyValue::Raw { num_bits, data }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::data::net::NetworkGUID;
+use crate::data::packet::SpawnedActor;
+use crate::data::replicator::{ReplicatedProperties, Rpc};
+use crate::uetypes::ChannelCloseReason;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Callbacks for what a `PacketParser` reads, every method does nothing by default. Visitors are
+/// `Send` so parsers can be moved to worker threads.
+pub trait ReplayVisitor: Send {
+    /// A frame starts being processed.
+    fn on_frame(&mut self, _time_seconds: f32) {}
+
+    /// An actor channel was opened, `actor` is the entry of the parser's `ActorRegistry`.
+    fn on_actor_spawned(&mut self, _actor: &SpawnedActor) {}
+
+    fn on_property_update(&mut self, _properties: &ReplicatedProperties) {}
+
+    fn on_rpc(&mut self, _rpc: &Rpc) {}
+
+    /// An actor channel was closed, `dormant` actors stay in the world.
+    fn on_channel_closed(&mut self, _channel: u32, _actor: Option<NetworkGUID>, _reason: ChannelCloseReason, _dormant: bool) {}
+
+    /// A net guid was exported with its path name.
+    fn on_net_guid_exported(&mut self, _net_guid: NetworkGUID, _path: &str) {}
+}
+
+fn lock<V: ?Sized>(visitor: &Mutex<V>) -> MutexGuard<'_, V> {
+    visitor.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Lets callers keep a handle on a visitor registered on a parser.
+impl<V: ReplayVisitor + ?Sized> ReplayVisitor for Arc<Mutex<V>> {
+    fn on_frame(&mut self, time_seconds: f32) {
+        lock(self).on_frame(time_seconds)
+    }
+
+    fn on_actor_spawned(&mut self, actor: &SpawnedActor) {
+        lock(self).on_actor_spawned(actor)
+    }
+
+    fn on_property_update(&mut self, properties: &ReplicatedProperties) {
+        lock(self).on_property_update(properties)
+    }
+
+    fn on_rpc(&mut self, rpc: &Rpc) {
+        lock(self).on_rpc(rpc)
+    }
+
+    fn on_channel_closed(&mut self, channel: u32, actor: Option<NetworkGUID>, reason: ChannelCloseReason, dormant: bool) {
+        lock(self).on_channel_closed(channel, actor, reason, dormant)
+    }
+
+    fn on_net_guid_exported(&mut self, net_guid: NetworkGUID, path: &str) {
+        lock(self).on_net_guid_exported(net_guid, path)
+    }
+}
@@ -1,9 +1,10 @@
-use crate::uchunk::{EventChunk, HeaderChunk};
+use crate::uchunk::{CheckpointChunk, EventChunk, HeaderChunk};
 use std::io::{Read, Cursor};
 use byteorder::ReadBytesExt;
-use crate::ureplay::UReplay;
+use crate::ureplay::{Chunk, ChunkType, ReplayChunk, ReplayMeta, UReplay};
+use crate::ResultExt;
 use serde::Deserialize;
-use crate::data::DataChunk;
+use crate::data::{DataChunk, Decompressor, OodleDecoder, Uncompressed};
 use crate::data::net::DemoFrame;
 use crate::data::packet::PacketParser;
 
@@ -15,6 +16,14 @@ pub struct Elimination {
     pub knocked: bool
 }
 
+/// A streaming level (such as a POI sub-level) becoming visible at `time_seconds`. Demo frames
+/// only record the levels that were added, so there are no matching unload events.
+#[derive(Debug, PartialEq)]
+pub struct LevelLoad {
+    pub time_seconds: f32,
+    pub package_name: String
+}
+
 #[derive(Debug, Default, Deserialize, PartialEq)]
 pub struct MatchStats {
     pub unknown: u32,
@@ -74,63 +83,131 @@ impl Elimination {
     }
 }
 
+/// How far `FNSkim` processes the replay data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkimDepth {
+    /// Header, events and stats only, data and checkpoint chunks are skipped.
+    Metadata,
+    /// Also decodes the frames of data chunks and keeps checkpoints.
+    Frames,
+    /// Also runs frames through a `PacketParser`, tracking channels and actors.
+    Packets,
+    /// Also reads the replicated content of actor bunches.
+    Replication
+}
+
 #[derive(Debug, Default)]
 pub struct FNSkim {
     pub header: HeaderChunk,
     pub team_stats: TeamStats,
     pub match_stats: MatchStats,
     pub eliminations: Vec<Elimination>,
-    #[cfg(target_os = "windows")]
-    pub data_chunks: Option<Vec<DemoFrame>>
+    pub meta: ReplayMeta,
+    /// Raw data chunks, only kept when skimming frames. They are decompressed one at a time by
+    /// `for_each_frame`.
+    pub data_chunks: Option<Vec<Chunk>>,
+    /// Checkpoints of the replay, only kept when skimming frames.
+    pub checkpoints: Vec<CheckpointChunk>,
+    /// Streaming levels in the order they were loaded, only filled when skimming frames. Unloads
+    /// (`bIsVisible == false`) are not recorded in replays and are not reported.
+    pub level_loads: Vec<LevelLoad>,
+    /// Parser the frames were run through, only kept when skimming packets. Replicated
+    /// properties, RPCs and channel events are taken from it.
+    pub packet_parser: Option<PacketParser>
 }
 
 impl FNSkim {
-    pub fn skim(replay: UReplay, data: bool) -> crate::Result<FNSkim> {
-        if data && !cfg!(target_os = "windows") {
-            return Err(crate::ErrorKind::ReplayParseError("Can't parse data as cant decompress data outside of windows".to_string()).into());
+    /// Skims the replay. Compressed replays are decompressed with `OodleDecoder`, pass another
+    /// decompressor such as `OodleLibrary` to `skim_with` to use the native library instead.
+    pub fn skim(replay: UReplay, depth: SkimDepth) -> crate::Result<FNSkim> {
+        if !replay.meta.is_compressed {
+            return FNSkim::skim_with(replay, depth, &Uncompressed);
         }
-        #[cfg(target_os = "windows")] let mut data_chunks: Vec<DataChunk> = Vec::new();
+        FNSkim::skim_with(replay, depth, &OodleDecoder)
+    }
+
+    pub fn skim_with(replay: UReplay, depth: SkimDepth, decompressor: &dyn Decompressor) -> crate::Result<FNSkim> {
+        FNSkim::skim_with_parser(replay, depth, decompressor, PacketParser::new())
+    }
+
+    /// Skims the replay running the frames through `packet_parser`, so visitors added to it see
+    /// the replay as it is read.
+    pub fn skim_with_parser(replay: UReplay, depth: SkimDepth, decompressor: &dyn Decompressor,
+                            mut packet_parser: PacketParser) -> crate::Result<FNSkim> {
+        let mut data_chunks: Vec<Chunk> = Vec::new();
         let mut skim = FNSkim::default();
-        for x in replay.chunks {
-            match x.variant {
-                0 => {
-                    skim.header = HeaderChunk::parse(x)?;
-                }
-                #[cfg(target_os = "windows")]
-                1 => {
-                    if data {
-                        data_chunks.push(DataChunk::parse(x, replay.meta.encryption_key.as_slice())?);
-                    }
+        packet_parser.set_replication(depth >= SkimDepth::Replication);
+        for (i, x) in replay.chunks.into_iter().enumerate() {
+            let offset = replay.index.get(i).map_or(0, |x| x.offset);
+            skim.skim_chunk(x, depth, &replay.meta, decompressor, &mut packet_parser, &mut data_chunks)
+                .chain_err(|| crate::ErrorKind::ReplayParseError(format!("failed to parse chunk {} at {}", i, offset)))?;
+        }
+        if depth >= SkimDepth::Frames {
+            skim.data_chunks = Some(data_chunks);
+        }
+        if depth >= SkimDepth::Packets {
+            skim.packet_parser = Some(packet_parser);
+        }
+        skim.meta = replay.meta;
+        Ok(skim)
+    }
+
+    /// Decompresses the kept data chunks one at a time and passes their frames to `f`, packets
+    /// are borrowed from the chunk being read.
+    pub fn for_each_frame<F: FnMut(DemoFrame<&[u8]>) -> crate::Result<()>>(&self, decompressor: &dyn Decompressor, mut f: F) -> crate::Result<()> {
+        for x in self.data_chunks.iter().flatten() {
+            let data_chunk = DataChunk::from_bytes(&x.data, &self.meta, decompressor)?;
+            for frame in DemoFrame::frames(&data_chunk, &self.header) {
+                f(frame?)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn skim_chunk(&mut self, x: Chunk, depth: SkimDepth, meta: &ReplayMeta, decompressor: &dyn Decompressor,
+                  packet_parser: &mut PacketParser, data_chunks: &mut Vec<Chunk>) -> crate::Result<()> {
+        if matches!(x.chunk_type(), ChunkType::ReplayData | ChunkType::Checkpoint) && depth == SkimDepth::Metadata {
+            return Ok(());
+        }
+        if x.chunk_type() == ChunkType::ReplayData {
+            let data_chunk = DataChunk::from_bytes(&x.data, meta, decompressor)?;
+            for frame in DemoFrame::frames(&data_chunk, &self.header) {
+                let frame = frame?;
+                if depth >= SkimDepth::Packets {
+                    packet_parser.received_frame(&frame)?;
                 }
-                3 => {
-                    let e_chunk = EventChunk::parse(x, replay.meta.encryption_key.as_slice())?;
-                    if e_chunk.group == "playerElim" {
-                        skim.eliminations.push(Elimination::parse(e_chunk)?);
-                    } else {
-                        match &*e_chunk.metadata {
-                            "AthenaMatchStats" => {
-                                skim.match_stats = bincode::deserialize(e_chunk.data.as_slice())?;
-                            }
-                            "AthenaTeamMatchStats" => {
-                                skim.team_stats = bincode::deserialize(e_chunk.data.as_slice())?;
-                            }
-                            _ => {}
+                self.level_loads.extend(frame.streaming_levels.iter().map(|x| LevelLoad {
+                    time_seconds: frame.time_seconds,
+                    package_name: x.package_name.clone()
+                }));
+            }
+            data_chunks.push(x);
+            return Ok(());
+        }
+        match x.decode(meta, decompressor)? {
+            ReplayChunk::Header(header) => {
+                self.header = header;
+            }
+            ReplayChunk::Checkpoint(checkpoint) => {
+                self.checkpoints.push(checkpoint);
+            }
+            ReplayChunk::Event(e_chunk) => {
+                if e_chunk.group == "playerElim" {
+                    self.eliminations.push(Elimination::parse(e_chunk)?);
+                } else {
+                    match &*e_chunk.metadata {
+                        "AthenaMatchStats" => {
+                            self.match_stats = bincode::deserialize(e_chunk.data.as_slice())?;
+                        }
+                        "AthenaTeamMatchStats" => {
+                            self.team_stats = bincode::deserialize(e_chunk.data.as_slice())?;
                         }
+                        _ => {}
                     }
                 }
-                _ => {}
             }
+            _ => {}
         }
-        #[cfg(target_os = "windows")]
-        if data {
-            let mut vec: Vec<DemoFrame> = Vec::new();
-            let mut packet_parser = PacketParser::new();
-            //todo in the future, don't packet parse by default and let user parse manually
-            for x in data_chunks {
-               vec.append(&mut DemoFrame::parse_data(x, &mut packet_parser)?);
-            }
-            skim.data_chunks = Some(vec);
-        }
-        Ok(skim)
+        Ok(())
     }
 }
\ No newline at end of file
@@ -1,6 +1,5 @@
-#![feature(test)]
+#![cfg_attr(feature = "bench", feature(test))]
 
-#[cfg(target_os = "windows")]
 pub mod data;
 pub mod fnchunk;
 pub mod uetypes;
@@ -17,9 +16,13 @@ error_chain! {
             description("replay parse failure")
             display("replay parse failure: {}", msg)
         }
-        OodleDecodeError {
+        OodleDecodeError(msg: String) {
             description("oodle decode failure")
-            display("oodle decode failure")
+            display("oodle decode failure: {}", msg)
+        }
+        DecryptionError(msg: String) {
+            description("replay decryption failure")
+            display("replay decryption failure: {}", msg)
         }
     }
     foreign_links {
@@ -28,15 +31,18 @@ error_chain! {
         Time(std::time::SystemTimeError);
         Enc(block_modes::BlockModeError);
         Iv(block_modes::InvalidKeyIvLength);
-        Native(libloading::Error);
+        Native(libloading::Error) #[cfg(feature = "oodle-library")];
     }
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "bench")]
     extern crate test;
-    use crate::fnchunk::FNSkim;
+    use crate::fnchunk::{FNSkim, SkimDepth};
     use crate::ureplay::UReplay;
+    use crate::uchunk::HeaderChunk;
+    #[cfg(feature = "bench")]
     use test::Bencher;
     use crate::uetypes::UEReadExt;
     use crate::data::BitReader;
@@ -46,11 +52,12 @@ mod tests {
     use crate::data::net::NetworkGUID;
     use crate::data::net::StringExt;
 
+    #[cfg(feature = "bench")]
     #[bench]
     fn parse_full_replay(b: &mut Bencher)  {
         let file = std::fs::read("season12.replay").unwrap();
         b.iter(|| {
-            FNSkim::skim(UReplay::parse(file.clone()).unwrap(), true);
+            FNSkim::skim(UReplay::parse(file.clone()).unwrap(), SkimDepth::Replication);
         });
     }
 
@@ -143,13 +150,23 @@ mod tests {
         let mut c: BitReader = BitReader::new(&mut c_bytes, c_bytes_len);
         let mut d: BitReader = BitReader::new(&mut d_bytes, d_bytes_len);
         let mut e: BitReader = BitReader::new(&mut e_bytes, e_bytes_len);
-        assert_eq!(a.read_packed_vector(10, 24).unwrap(), FVector(176286 as f32, -167520 as f32, -2618 as f32));
-        assert_eq!(b.read_packed_vector(10, 24).unwrap(), FVector(181237 as f32, -172272 as f32, -2235 as f32));
-        assert_eq!(c.read_packed_vector(100, 30).unwrap(), FVector(179955 as f32, -181401 as f32, -2192 as f32));
-        assert_eq!(d.read_packed_vector(100, 30).unwrap(), FVector(188546 as f32, -175249 as f32, -2610 as f32));
+        // (float)((int32)D - Bias) / Scale as in UE's ReadPackedVector
+        assert_eq!(a.read_packed_vector(10, 24).unwrap(), FVector(176286.1, -167520.3, -2618.1));
+        assert_eq!(b.read_packed_vector(10, 24).unwrap(), FVector(181237.9, -172272.8, -2235.1));
+        assert_eq!(c.read_packed_vector(100, 30).unwrap(), FVector(179955.56, -181401.46, -2192.08));
+        assert_eq!(d.read_packed_vector(100, 30).unwrap(), FVector(188546.12, -175249.68, -2610.85));
         assert_eq!(e.read_packed_vector(1, 24).unwrap(), FVector(0 as f32, 0 as f32, 0 as f32))
     }
 
+    #[test]
+    fn byte_rotation() {
+        use crate::data::packet::FRotator;
+        let bytes = pack_bits(&[(1, 1), (1, 8), (1, 1), (64, 8), (1, 1), (255, 8)]);
+        let mut slice = bytes.as_slice();
+        let rotation = BitReader::new(&mut slice, 27).read_rotation_byte().unwrap();
+        assert_eq!(rotation, FRotator(1.40625, 90.0, 358.59375));
+    }
+
     #[test]
     fn netguid() {
         assert_eq!(NetworkGUID(1u32), NetworkGUID(1u32));
@@ -183,4 +200,954 @@ mod tests {
         assert_eq!("Set", "DamageSet".to_string().remove_path_prefix("Damage".to_string()));
         assert_eq!("DamageSet", "DamageSet".to_string().remove_path_prefix("".to_string()));
     }
+
+    #[test]
+    fn uncompressed_data_chunk() {
+        use crate::ureplay::Chunk;
+        use crate::uchunk::Aes;
+        use crate::data::{DataChunk, Decompressor, Uncompressed};
+        use crate::ureplay::ReplayMeta;
+        use crate::uetypes::FileVersion;
+        use block_modes::BlockMode;
+        struct Failing;
+        impl Decompressor for Failing {
+            fn decompress(&self, _uncompressed_size: u64, _bytes: &[u8]) -> crate::Result<Vec<u8>> {
+                Err(crate::ErrorKind::OodleDecodeError("called".to_string()).into())
+            }
+        }
+        let key = [7u8; 32];
+        let payload = vec![1u8, 2, 3, 4, 5];
+        let encrypted = Aes::new_var(&key, Default::default()).unwrap().encrypt_vec(payload.as_slice());
+        let mut data = Vec::new();
+        for x in &[0u32, 1000, encrypted.len() as u32, payload.len() as u32] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(encrypted.as_slice());
+        let meta = ReplayMeta { file_version: FileVersion::Encryption as u32, is_encrypted: true, encryption_key: key.to_vec(), ..Default::default() };
+        let chunk = DataChunk::from_bytes(&data, &meta, &Failing).unwrap();
+        assert_eq!(chunk.end, 1000);
+        assert_eq!(chunk.data, payload);
+        // the whole chunk is the payload before stream chunk times
+        let meta = ReplayMeta { file_version: FileVersion::RecordedTimestamp as u32, ..Default::default() };
+        let chunk = DataChunk::from_bytes(&payload, &meta, &Uncompressed).unwrap();
+        assert_eq!((chunk.start, chunk.end, chunk.length, chunk.memory_size_in_bytes), (0, 0, 5, 5));
+        assert_eq!(chunk.data, payload);
+        let meta = ReplayMeta { file_version: FileVersion::StreamChunkTimes as u32, ..Default::default() };
+        let mut data = Vec::new();
+        for x in &[0u32, 1000, payload.len() as u32 + 1] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(payload.as_slice());
+        assert!(DataChunk::from_bytes(&data, &meta, &Uncompressed).is_err());
+        data.push(6);
+        assert_eq!(DataChunk::from_bytes(&data, &meta, &Uncompressed).unwrap().data, vec![1, 2, 3, 4, 5, 6]);
+
+        // compressed payloads are prefixed with their sizes
+        let mut data = Vec::new();
+        for x in &[0u32, 1000, payload.len() as u32 + 8, 0, payload.len() as u32, payload.len() as u32] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(payload.as_slice());
+        let meta = ReplayMeta { file_version: FileVersion::Encryption as u32, is_compressed: true, ..Default::default() };
+        assert_eq!(DataChunk::from_bytes(&data, &meta, &Uncompressed).unwrap().data, payload);
+        assert!(DataChunk::from_bytes(&data, &meta, &Failing).is_err());
+        for sizes in &[[5u32, 6], [5, u32::MAX], [u32::MAX, 5]] {
+            let mut data = Vec::new();
+            for x in &[0u32, 1000, 13, 0, sizes[0], sizes[1]] {
+                data.extend_from_slice(&x.to_le_bytes());
+            }
+            data.extend_from_slice(payload.as_slice());
+            assert!(DataChunk::from_bytes(&data, &meta, &Uncompressed).is_err());
+        }
+    }
+
+    #[test]
+    fn oodle_stored_blocks() {
+        use crate::data::oodle::decompress;
+        // uncompressed block
+        assert_eq!(decompress(&[0x4C, 0x06, 1, 2, 3], 3).unwrap(), vec![1, 2, 3]);
+        // memset quantum
+        assert_eq!(decompress(&[0x8C, 0x06, 0x07, 0xFF, 0xFF, 9], 4).unwrap(), vec![9; 4]);
+        // quantum stored as is
+        assert_eq!(decompress(&[0x8C, 0x0A, 0x00, 0x00, 0x02, 4, 5, 6], 3).unwrap(), vec![4, 5, 6]);
+        assert!(decompress(&[0x8C, 0x0C, 0x00, 0x00, 0x02, 4, 5, 6], 3).is_err());
+        assert!(decompress(&[0x4C, 0x06, 1, 2, 3, 4], 3).is_err());
+    }
+
+    #[test]
+    fn oodle_entropy_chunk() {
+        use crate::data::oodle::decompress;
+        // single rle block of 100 bytes in a kraken quantum
+        let quantum = [0x30, 0x01, 0x8C, 0x00, 0x01, 0x2A];
+        let mut src = vec![0x8C, 0x06, 0x00, 0x00, quantum.len() as u8 - 1];
+        src.extend_from_slice(&quantum);
+        assert_eq!(decompress(&src, 100).unwrap(), vec![0x2A; 100]);
+    }
+
+    /// Input of the Oodle fixtures: 12000 bytes of words, back references and small literals,
+    /// repeated with two bytes changed in every copy.
+    fn oodle_fixture_input() -> Vec<u8> {
+        let words: [&[u8]; 9] = [b"replay", b"chunk", b"frame", b"packet", b"actor", b"guid", &[0; 4], &[1, 0], &[0xFF, 0xFE]];
+        let mut state = 0x2545F491u32;
+        let mut base: Vec<u8> = Vec::new();
+        while base.len() < 12000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let r = (state >> 16) as usize;
+            match r % 8 {
+                k if k < 5 && base.len() > 64 => {
+                    let start = base.len() - 1 - (r >> 3) % std::cmp::min(base.len(), 1024);
+                    for i in 0..8 + r % 32 {
+                        base.push(base[start + i]);
+                    }
+                }
+                k if k < 7 => base.extend_from_slice(words[(r >> 3) % words.len()]),
+                _ => base.push((r >> 3) as u8 & 0x1F)
+            }
+        }
+        base.truncate(12000);
+        let mut input = Vec::new();
+        for i in 0..300000 / base.len() {
+            let mut part = base.clone();
+            part[i * 37 % base.len()] ^= 0x55;
+            part[i * 101 % base.len()] = i as u8;
+            input.extend_from_slice(&part);
+        }
+        input
+    }
+
+    #[test]
+    fn oodle_compressed_chunks() {
+        use crate::data::oodle::decompress;
+        // compressed with OodleLZ_Compress of oo2core_7_win64.dll, Selkie at the normal level
+        // writes the same stream as Mermaid
+        let input = oodle_fixture_input();
+        assert_eq!(input.len(), 300000);
+        for fixture in &[
+            &include_bytes!("data/oodle/testdata/kraken_superfast.bin")[..],
+            &include_bytes!("data/oodle/testdata/kraken_normal.bin")[..],
+            &include_bytes!("data/oodle/testdata/mermaid_superfast.bin")[..],
+            &include_bytes!("data/oodle/testdata/mermaid_normal.bin")[..],
+            &include_bytes!("data/oodle/testdata/selkie_superfast.bin")[..]
+        ] {
+            assert!(decompress(fixture, input.len()).unwrap() == input);
+            assert!(decompress(&fixture[..fixture.len() - 1], input.len()).is_err());
+        }
+    }
+
+    #[cfg(feature = "oodle-library")]
+    #[test]
+    fn oodle_library() {
+        use crate::data::OodleLibrary;
+        fn shared<T: Send + Sync>() {}
+        shared::<OodleLibrary>();
+        assert!(OodleLibrary::load("./missing_oodle_library").is_err());
+    }
+
+    /// Appends `s` as an ASCII FString, its length includes the null terminator.
+    fn fstring(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as i32 + 1).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+        out.push(0);
+    }
+
+    /// Id, group, metadata and times of an event chunk, followed by its payload.
+    fn event_header(start_time: u32, end_time: u32) -> Vec<u8> {
+        let mut event = Vec::new();
+        for x in &["id", "group", "meta"] {
+            fstring(&mut event, x);
+        }
+        event.extend_from_slice(&start_time.to_le_bytes());
+        event.extend_from_slice(&end_time.to_le_bytes());
+        event
+    }
+
+    fn export(handle: u32, name: &str) -> Option<crate::data::net::NetFieldExport> {
+        Some(crate::data::net::NetFieldExport { handle, compatible_checksum: 0, name: name.to_string() })
+    }
+
+    /// Packs `(value, bit count)` fields least significant bit first.
+    fn pack_bits(fields: &[(u32, usize)]) -> Vec<u8> {
+        let bits: Vec<bool> = fields.iter().flat_map(|(value, count)| (0..*count).map(move |i| value >> i & 1 == 1)).collect();
+        let mut bytes = vec![0u8; (bits.len() + 7) / 8];
+        for (i, bit) in bits.iter().enumerate() {
+            bytes[i / 8] |= (*bit as u8) << (i % 8);
+        }
+        bytes
+    }
+
+    /// Packet opening channel 3 for static actor 3 with the given content blocks and closing it
+    /// for `reason`.
+    fn actor_channel_packet(reason: u32, content: &[(u32, usize)]) -> Vec<u8> {
+        let content_bits: usize = content.iter().map(|x| x.1).sum();
+        let mut fields = vec![
+            (0b111, 3), // control, open, close
+            (reason, 4),
+            (0b10, 2), // unpaused, reliable
+            (6, 8), // channel 3
+            (0, 3),
+            (1, 1),
+            (0xCC, 8), // Actor
+            (8 + content_bits as u32, 14),
+            (6, 8) // static actor guid 3
+        ];
+        fields.extend_from_slice(content);
+        fields.push((1, 1)); // end of packet
+        pack_bits(&fields)
+    }
+
+    /// Header chunk of the given network version, 16 and up record Fortnite's flags.
+    fn header_chunk(version: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for x in &[0x2CF5A13Du32, version] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        if version >= 19 {
+            bytes.extend_from_slice(&0i32.to_le_bytes());
+        }
+        for x in &[1u32, 2, 3] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        if version >= 12 {
+            bytes.extend_from_slice(&[1u8; 16]);
+        }
+        if version >= 17 {
+            bytes.extend_from_slice(&[0u8; 8]);
+        }
+        if version >= 11 {
+            bytes.extend_from_slice(&[4, 0, 26, 0, 0, 0]);
+        }
+        bytes.extend_from_slice(&1234u32.to_le_bytes());
+        if version >= 11 {
+            bytes.extend_from_slice(&0i32.to_le_bytes()); // branch
+        }
+        if version >= 6 {
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+        }
+        fstring(&mut bytes, "Apol");
+        if version >= 7 {
+            bytes.extend_from_slice(&700u32.to_le_bytes());
+        }
+        if version >= 9 {
+            bytes.extend_from_slice(&10u32.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // game specific data
+        if version >= 18 {
+            bytes.extend_from_slice(&[0u8; 16]);
+            fstring(&mut bytes, "Win");
+            bytes.extend_from_slice(&[4, 3]);
+        }
+        bytes
+    }
+
+    /// Replay file with a minimal header followed by the given chunks.
+    fn replay_bytes(chunks: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for x in &[0x1CA2E27Fu32, 6, 60000, 16, 1234] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        fstring(&mut bytes, &format!("{:256}", "test")); // padded to a fixed size
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // is_live
+        bytes.extend_from_slice(&0u64.to_le_bytes()); // timestamp
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // is_compressed
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // is_encrypted
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // encryption_key
+        for (variant, data) in chunks {
+            bytes.extend_from_slice(&variant.to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    #[test]
+    fn streaming_replay() {
+        let bytes = replay_bytes(&[(0, &[1, 2, 3]), (3, &[4, 5])]);
+        let mut reader = UReplay::from_reader(std::io::Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(reader.meta.friendly_name, "test");
+        assert_eq!(reader.meta.length_in_ms, 60000);
+        let infos = reader.by_ref().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[1].variant, 3);
+        assert_eq!(infos[1].size, 2);
+        assert_eq!(infos[1].offset, bytes.len() as u64 - 2);
+        assert_eq!(reader.read_chunk(&infos[0]).unwrap().data, vec![1, 2, 3]);
+        assert_eq!(UReplay::parse(bytes).unwrap().chunks.len(), 2);
+
+        let mut truncated = replay_bytes(&[(1, &[1, 2, 3])]);
+        truncated.pop();
+        assert!(UReplay::parse(truncated).is_err());
+    }
+
+    #[test]
+    fn chunk_index() {
+        let event = event_header(3000, 4000);
+        let mut data = Vec::new();
+        data.extend_from_slice(&1000u32.to_le_bytes());
+        data.extend_from_slice(&2000u32.to_le_bytes());
+        let bytes = replay_bytes(&[(0, &[0]), (1, &data), (3, &event)]);
+        let len = bytes.len() as u64;
+        let replay = UReplay::parse(bytes).unwrap();
+        assert_eq!(replay.index.len(), 3);
+        assert_eq!(replay.index[0].start_time, None);
+        assert_eq!((replay.index[1].start_time, replay.index[1].end_time), (Some(1000), Some(2000)));
+        assert_eq!((replay.index[2].start_time, replay.index[2].end_time), (Some(3000), Some(4000)));
+        assert_eq!(replay.index[2].offset + replay.index[2].size as u64, len);
+        let between = replay.chunks_between(1500, 2500);
+        assert_eq!(between.len(), 1);
+        assert_eq!(between[0].variant, 1);
+
+        let replay = UReplay::parse(replay_bytes(&[(0, &[0]), (1, &[1, 2])])).unwrap();
+        assert_eq!((replay.index[1].start_time, replay.index[1].end_time), (None, None));
+    }
+
+    #[test]
+    fn chunk_types() {
+        use crate::ureplay::{Chunk, ChunkType, ReplayChunk};
+        use crate::data::Uncompressed;
+        assert_eq!(ChunkType::from(2), ChunkType::Checkpoint);
+        assert_eq!(ChunkType::from(9), ChunkType::Unknown(9));
+        assert_eq!(u32::from(ChunkType::Event), 3);
+        let replay = UReplay::parse(replay_bytes(&[])).unwrap();
+        match (Chunk { variant: 9, data: vec![1] }).decode(&replay.meta, &Uncompressed).unwrap() {
+            ReplayChunk::Unknown(chunk) => assert_eq!(chunk.data, vec![1]),
+            other => panic!("unexpected {:?}", other)
+        }
+        assert_eq!((Chunk { variant: 3, data: vec![] }).chunk_type(), ChunkType::Event);
+    }
+
+    #[test]
+    fn checkpoint_chunk() {
+        use crate::uchunk::{Aes, CheckpointChunk, ReplayHeaderFlags};
+        use crate::ureplay::{Chunk, ReplayMeta};
+        use crate::data::Uncompressed;
+        use crate::data::packet::PacketParser;
+        use block_modes::BlockMode;
+        let key = [7u8; 32];
+        let mut state = Vec::new();
+        state.extend_from_slice(&1234u64.to_le_bytes()); // packet offset
+        state.extend_from_slice(&0i32.to_le_bytes()); // level
+        state.extend_from_slice(&1i32.to_le_bytes());
+        fstring(&mut state, "/Game/Door");
+        state.extend_from_slice(&2i32.to_le_bytes()); // guid cache
+        state.extend_from_slice(&[4, 0, 1]);
+        fstring(&mut state, "/Game/Athena.PlayerPawn_C");
+        state.extend_from_slice(&[0, 8, 0, 0, 0, 0]);
+        state.extend_from_slice(&1u32.to_le_bytes()); // export groups
+        fstring(&mut state, "/Script/Pawn");
+        state.extend_from_slice(&[2, 2, 1, 0, 9, 0, 0, 0, 1, 0]);
+        state.extend_from_slice(&0u32.to_le_bytes()); // frame level
+        state.extend_from_slice(&1.5f32.to_le_bytes());
+        state.extend_from_slice(&[0, 0, 0]);
+        state.extend_from_slice(&[0u8; 8]);
+        state.push(0);
+        state.extend_from_slice(&[0u8; 8]);
+        state.push(0);
+        state.extend_from_slice(&0u32.to_le_bytes());
+        let encrypted = Aes::new_var(&key, Default::default()).unwrap().encrypt_vec(state.as_slice());
+        let mut data = Vec::new();
+        for x in &["1", "checkpoint", ""] {
+            fstring(&mut data, x);
+        }
+        for x in &[60000u32, 90000, encrypted.len() as u32] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(encrypted.as_slice());
+        let meta = ReplayMeta { file_version: 6, is_encrypted: true, encryption_key: key.to_vec(), ..Default::default() };
+        let checkpoint = CheckpointChunk::from_bytes(&data, &meta, &Uncompressed).unwrap();
+        assert_eq!(checkpoint.start_time, 60000);
+        assert_eq!(checkpoint.data, state);
+        let flags = ReplayHeaderFlags::HAS_STREAMING_FIXES | ReplayHeaderFlags::GAME_SPECIFIC_FRAME_DATA;
+        let header = HeaderChunk { network_version: 16, flags, ..Default::default() };
+        let (parser, state) = PacketParser::from_checkpoint(&checkpoint, &header).unwrap();
+        assert_eq!(state.packet_offset, Some(1234));
+        assert_eq!(state.deleted_startup_actors, vec!["/Game/Door".to_string()]);
+        assert_eq!(state.export_groups[0].path_name, "/Script/Pawn");
+        assert_eq!(state.export_groups[0].exports.len(), 1);
+        assert_eq!(state.frame.time_seconds, 1.5);
+        assert_eq!(parser.net_guid_cache.net_guid_to_path.get(&NetworkGUID(4)), Some(&"PlayerPawn_C".to_string()));
+    }
+
+    #[test]
+    fn write_replay() {
+        use crate::ureplay::UReplay;
+        let bytes = replay_bytes(&[(7, &[1, 2, 3]), (9, &[])]);
+        assert_eq!(UReplay::parse(bytes.clone()).unwrap().to_bytes().unwrap(), bytes);
+        let mut replay = UReplay::parse(bytes).unwrap();
+        replay.meta.friendly_name = "Ünsaved Replay".to_string();
+        replay.meta.is_live = true;
+        let written = replay.to_bytes().unwrap();
+        let reparsed = UReplay::parse(written.clone()).unwrap();
+        assert_eq!(reparsed.meta, replay.meta);
+        assert_eq!(reparsed.chunks, replay.chunks);
+        assert_eq!(UReplay::parse(written.clone()).unwrap().to_bytes().unwrap(), written);
+        assert_eq!(bincode::serialize("test").unwrap(), vec![5, 0, 0, 0, b't', b'e', b's', b't', 0]);
+        assert_eq!(bincode::serialize("").unwrap(), vec![0, 0, 0, 0]);
+        assert_eq!(bincode::serialize("é").unwrap(), vec![0xFE, 0xFF, 0xFF, 0xFF, 0xE9, 0, 0, 0]);
+        assert_eq!(bincode::serialize(&true).unwrap(), vec![1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn conditional_decryption() {
+        use crate::uchunk::Aes;
+        use crate::ureplay::{Chunk, ReplayChunk};
+        use crate::data::Uncompressed;
+        use block_modes::BlockMode;
+        let mut event = event_header(0, 0);
+        let mut plain = event.clone();
+        plain.extend_from_slice(&3u32.to_le_bytes());
+        plain.extend_from_slice(&[1, 2, 3]);
+        let mut replay = UReplay::parse(replay_bytes(&[(3, &plain)])).unwrap();
+        match replay.chunks.remove(0).decode(&replay.meta, &Uncompressed).unwrap() {
+            ReplayChunk::Event(e) => assert_eq!(e.data, vec![1, 2, 3]),
+            other => panic!("unexpected {:?}", other)
+        }
+
+        let key = [7u8; 32];
+        let encrypted = Aes::new_var(&key, Default::default()).unwrap().encrypt_vec(&[1, 2, 3]);
+        event.extend_from_slice(&(encrypted.len() as u32).to_le_bytes());
+        event.extend_from_slice(encrypted.as_slice());
+        let chunk = || Chunk { variant: 3, data: event.clone() };
+        replay.meta.is_encrypted = true;
+        let error = chunk().decode(&replay.meta, &Uncompressed).unwrap_err();
+        assert!(error.to_string().contains("has no key"));
+        replay.set_encryption_key(vec![7u8; 16]);
+        let error = chunk().decode(&replay.meta, &Uncompressed).unwrap_err();
+        assert!(error.to_string().contains("32 byte key"));
+        replay.set_encryption_key(key.to_vec());
+        match chunk().decode(&replay.meta, &Uncompressed).unwrap() {
+            ReplayChunk::Event(e) => assert_eq!(e.data, vec![1, 2, 3]),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn decrypt_replay() {
+        use crate::uchunk::encrypt;
+        use crate::uetypes::FileVersion;
+        use crate::ureplay::ReplayChunk;
+        use crate::data::Uncompressed;
+        let key = [7u8; 32];
+        let mut event = event_header(0, 0);
+        let encrypted = encrypt(&[1, 2, 3], &key).unwrap();
+        event.extend_from_slice(&(encrypted.len() as u32).to_le_bytes());
+        event.extend_from_slice(encrypted.as_slice());
+        let mut data = Vec::new();
+        let encrypted = encrypt(&[4, 5], &key).unwrap();
+        for x in &[0u32, 1000, encrypted.len() as u32, 2] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(encrypted.as_slice());
+        let mut replay = UReplay::parse(replay_bytes(&[(1, &data), (3, &event)])).unwrap();
+        replay.meta.is_encrypted = true;
+        replay.set_encryption_key(key.to_vec());
+
+        replay.decrypt().unwrap();
+        assert!(!replay.meta.is_encrypted);
+        assert_eq!(replay.index[1].offset, replay.index[0].offset + replay.index[0].size as u64 + 8);
+        let decrypted = UReplay::parse(replay.to_bytes().unwrap()).unwrap();
+        let meta = decrypted.meta;
+        let decoded: Vec<ReplayChunk> = decrypted.chunks.into_iter()
+            .map(|x| x.decode(&meta, &Uncompressed).unwrap()).collect();
+        match (&decoded[0], &decoded[1]) {
+            (ReplayChunk::Data(d), ReplayChunk::Event(e)) => {
+                assert_eq!(d.data, vec![4, 5]);
+                assert_eq!(e.data, vec![1, 2, 3]);
+            }
+            other => panic!("unexpected {:?}", other)
+        }
+
+        replay.encrypt(key.to_vec()).unwrap();
+        assert!(replay.encrypt(key.to_vec()).is_err());
+        let mut old_version = UReplay::parse(replay_bytes(&[])).unwrap();
+        old_version.meta.file_version = FileVersion::FriendlyNameEncoding as u32;
+        assert!(old_version.encrypt(key.to_vec()).is_err());
+        let mut encrypted = UReplay::parse(replay.to_bytes().unwrap()).unwrap();
+        assert!(encrypted.meta.is_encrypted);
+        match encrypted.chunks.remove(1).decode(&encrypted.meta, &Uncompressed).unwrap() {
+            ReplayChunk::Event(e) => assert_eq!(e.data, vec![1, 2, 3]),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn file_versions() {
+        let mut bytes = replay_bytes(&[]);
+        bytes[0] = 0;
+        assert!(UReplay::parse(bytes).unwrap_err().to_string().contains("not a replay file"));
+        let mut bytes = replay_bytes(&[]);
+        bytes[4] = 8;
+        let error = UReplay::parse(bytes).unwrap_err();
+        assert!(error.iter().any(|x| x.to_string().contains("unsupported replay file version 8")));
+
+        // version 2 has no timestamp and no encryption fields
+        let mut bytes = Vec::new();
+        for x in &[0x1CA2E27Fu32, 2, 60000, 16, 1234] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        fstring(&mut bytes, &format!("{:256}", "test")); // padded to a fixed size
+        bytes.extend_from_slice(&1u32.to_le_bytes()); // is_live
+        bytes.extend_from_slice(&1u32.to_le_bytes()); // is_compressed
+        bytes.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 9]);
+        let replay = UReplay::parse(bytes).unwrap();
+        assert!(replay.meta.is_live && replay.meta.is_compressed && !replay.meta.is_encrypted);
+        assert_eq!(replay.chunks[0].data, vec![9]);
+
+        let mut replay = UReplay::parse(replay_bytes(&[])).unwrap();
+        replay.meta.file_version = 7;
+        replay.meta.custom_versions.push((bincode::deserialize(&[3u8; 16]).unwrap(), 42));
+        let written = replay.to_bytes().unwrap();
+        assert_eq!(UReplay::parse(written).unwrap().meta, replay.meta);
+    }
+
+    #[test]
+    fn header_versions() {
+        use crate::uchunk::ReplayHeaderFlags;
+        use crate::uetypes::BuildConfig;
+        let old = HeaderChunk::from_bytes(&header_chunk(5)).unwrap();
+        assert_eq!(old.changelist, 1234);
+        assert_eq!(old.level_names_and_times, vec![("Apol".to_string(), 0)]);
+        assert_eq!(old.flags, ReplayHeaderFlags::default());
+        let fortnite = HeaderChunk::from_bytes(&header_chunk(16)).unwrap();
+        assert_eq!((fortnite.major, fortnite.minor), (4, 26));
+        assert_eq!(fortnite.level_names_and_times, vec![("Apol".to_string(), 700)]);
+        assert!(fortnite.flags.contains(ReplayHeaderFlags::HAS_STREAMING_FIXES));
+        assert!(fortnite.flags.contains(ReplayHeaderFlags::GAME_SPECIFIC_FRAME_DATA));
+        assert!(!fortnite.flags.contains(ReplayHeaderFlags::CLIENT_RECORDED));
+        let newest = HeaderChunk::from_bytes(&header_chunk(19)).unwrap();
+        assert_eq!(newest.platform, "Win");
+        assert_eq!(newest.build_config, BuildConfig::Shipping);
+        assert!(HeaderChunk::from_bytes(&[0u8; 8]).is_err());
+    }
+
+    #[test]
+    fn frame_layouts() {
+        use crate::data::net::{DemoFrame, StreamingLevel};
+        use crate::uchunk::ReplayHeaderFlags;
+        // network version 9 without streaming fixes: no export data, full streaming levels
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&0.5f32.to_le_bytes());
+        bytes.extend_from_slice(&1i32.to_le_bytes()); // one streaming level
+        for x in &["/Game/A", "/Game/A"] {
+            fstring(&mut bytes, x);
+        }
+        bytes.extend_from_slice(&[0u8; 40]);
+        bytes.push(0); // external data
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        let header = HeaderChunk { network_version: 9, ..Default::default() };
+        let mut cursor = bytes.as_slice();
+        let frame = DemoFrame::parse(&mut cursor, &header).unwrap();
+        assert_eq!(frame.current_level_index, 2);
+        assert_eq!(frame.time_seconds, 0.5);
+        assert_eq!(frame.streaming_levels[0].package_name, "/Game/A");
+        assert_eq!(frame.streaming_levels[0].package_name_to_load, Some("/Game/A".to_string()));
+        assert!(frame.streaming_levels[0].transform.is_some());
+        assert!(cursor.is_empty());
+
+        // streaming fixes with game specific frame data
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 2]);
+        fstring(&mut bytes, "POI");
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        bytes.extend_from_slice(&[9, 9]);
+        bytes.push(0);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        let flags = ReplayHeaderFlags::HAS_STREAMING_FIXES | ReplayHeaderFlags::GAME_SPECIFIC_FRAME_DATA;
+        let header = HeaderChunk { network_version: 16, flags, ..Default::default() };
+        let mut cursor = bytes.as_slice();
+        let frame = DemoFrame::parse(&mut cursor, &header).unwrap();
+        assert_eq!(frame.streaming_levels, vec![StreamingLevel { package_name: "POI".to_string(), ..Default::default() }]);
+        assert!(cursor.is_empty());
+        let oversized = bytes.iter().position(|x| *x == 9).unwrap() - 8;
+        bytes[oversized..oversized + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(DemoFrame::parse(&mut bytes.as_slice(), &header).is_err());
+    }
+
+    #[test]
+    fn external_data() {
+        use crate::data::net::DemoFrame;
+        use crate::data::packet::PacketParser;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        bytes.extend_from_slice(&0i32.to_le_bytes()); // streaming levels
+        bytes.extend_from_slice(&[20, 8, 0xAB, 0x0C]); // 10 bits for guid 4
+        bytes.push(0);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        let header = HeaderChunk { network_version: 9, ..Default::default() };
+        let frame = DemoFrame::parse(&mut bytes.as_slice(), &header).unwrap();
+        let mut parser = PacketParser::new();
+        parser.received_frame(&frame).unwrap();
+        assert!(parser.external_data(&NetworkGUID(4)).is_empty());
+        parser.collect_external_data();
+        parser.received_frame(&frame).unwrap();
+        assert_eq!(frame.external_data.len(), 1);
+        assert_eq!(frame.external_data[0].num_bits, 10);
+        assert_eq!(frame.external_data[0].payload, vec![0xAB, 0x0C]);
+        assert_eq!(parser.external_data(&NetworkGUID(4)), frame.external_data.as_slice());
+    }
+
+    #[test]
+    fn frame_iterator() {
+        use crate::data::DataChunk;
+        use crate::data::net::{DemoFrame, PacketState};
+        use crate::data::packet::PacketParser;
+        let mut data = Vec::new();
+        for time in &[1.0f32, 2.0] {
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&time.to_le_bytes());
+            data.extend_from_slice(&0i32.to_le_bytes()); // streaming levels
+            data.push(0); // external data
+            data.extend_from_slice(&1u32.to_le_bytes());
+            data.push(0x01); // empty packet
+            data.extend_from_slice(&0u32.to_le_bytes());
+        }
+        let chunk = DataChunk { start: 0, end: 0, length: 0, memory_size_in_bytes: 0, data };
+        let header = HeaderChunk { network_version: 9, ..Default::default() };
+        let frames = DemoFrame::frames(&chunk, &header).collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].time_seconds, 2.0);
+        assert_eq!(frames[0].packets, vec![&[0x01u8][..]]);
+        assert!(std::ptr::eq(frames[0].packets[0].as_ptr(), &chunk.data[17]));
+        let mut parser = PacketParser::new();
+        for frame in &frames {
+            parser.received_frame(frame).unwrap();
+        }
+        let owned = frames.into_iter().next().unwrap().into_owned();
+        assert_eq!(owned.packets.len(), 2);
+        assert!(owned.packets[1].state == PacketState::End);
+
+        let truncated = DataChunk { data: chunk.data[..23].to_vec(), ..chunk };
+        let mut frames = DemoFrame::frames(&truncated, &header);
+        assert!(frames.next().unwrap().is_ok());
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+    }
+
+    #[test]
+    fn skim_depth() {
+        let mut data = Vec::new();
+        for x in &[0u32, 1000, 3, 3] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(&[1, 2, 3]); // not a valid frame
+        let skim = FNSkim::skim(UReplay::parse(replay_bytes(&[(1, &data)])).unwrap(), SkimDepth::Metadata).unwrap();
+        assert!(skim.data_chunks.is_none());
+        assert!(skim.for_each_frame(&crate::data::Uncompressed, |_| panic!("no frames")).is_ok());
+        assert!(FNSkim::skim(UReplay::parse(replay_bytes(&[(1, &data)])).unwrap(), SkimDepth::Frames).is_err());
+    }
+
+    #[test]
+    fn skim_replication() {
+        use crate::data::schema::PropertyValue;
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&0u32.to_le_bytes());
+        frame.extend_from_slice(&1.0f32.to_le_bytes());
+        frame.extend_from_slice(&[2, 14, 2]); // one export of group 7
+        fstring(&mut frame, "/Game/Pawn.Pawn_C");
+        frame.extend_from_slice(&[6, 1, 4]); // 3 exports, handle 2
+        frame.extend_from_slice(&0u32.to_le_bytes());
+        frame.push(0);
+        fstring(&mut frame, "Health");
+        frame.extend_from_slice(&0u32.to_le_bytes());
+        let mut uobject = vec![6, 1, 0]; // guid 3 with a path
+        fstring(&mut uobject, "/Game/Pawn.Pawn_C");
+        frame.push(2);
+        frame.extend_from_slice(&(uobject.len() as i32).to_le_bytes());
+        frame.extend_from_slice(&uobject);
+        frame.push(0); // streaming levels
+        frame.extend_from_slice(&0u64.to_le_bytes());
+        frame.push(0); // external data
+        frame.extend_from_slice(&0u64.to_le_bytes());
+        // Health of the actor set to 0xAB
+        let packet = actor_channel_packet(1, &[(0b11, 2), (33 << 1, 8), (0, 1), (3 << 1, 8), (8 << 1, 8), (0xAB, 8), (0, 8)]);
+        frame.push(0);
+        frame.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&packet);
+        frame.push(0);
+        frame.extend_from_slice(&0u32.to_le_bytes());
+        let mut data = Vec::new();
+        for x in &[0u32, 1000, frame.len() as u32, frame.len() as u32] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        data.extend_from_slice(&frame);
+        let bytes = replay_bytes(&[(0, &header_chunk(16)), (1, &data)]);
+
+        let skim = FNSkim::skim(UReplay::parse(bytes.clone()).unwrap(), SkimDepth::Frames).unwrap();
+        assert!(skim.packet_parser.is_none());
+        let mut parser = FNSkim::skim(UReplay::parse(bytes.clone()).unwrap(), SkimDepth::Packets).unwrap().packet_parser.unwrap();
+        assert!(parser.actors.get(&NetworkGUID(3)).is_some());
+        assert!(parser.take_replicated().is_empty());
+        let mut parser = FNSkim::skim(UReplay::parse(bytes).unwrap(), SkimDepth::Replication).unwrap().packet_parser.unwrap();
+        let replicated = parser.take_replicated();
+        assert_eq!(replicated.len(), 1);
+        assert_eq!(replicated[0].actor, NetworkGUID(3));
+        assert_eq!(replicated[0].properties[0].name, "Health");
+        assert_eq!(replicated[0].properties[0].value, PropertyValue::Raw { num_bits: 8, data: vec![0xAB] });
+    }
+
+    #[test]
+    fn replicator() {
+        use crate::data::net::NetFieldExports;
+        use crate::data::replicator::{self, NetFieldExportGroupMap};
+        use crate::data::schema::{PropertyType, PropertyValue, SchemaRegistry};
+        let mut groups = NetFieldExportGroupMap::default();
+        groups.received_exports(&[
+            NetFieldExports {
+                path_name_index: 7,
+                is_exported: true,
+                path_name: Some("/Game/Pawn.Pawn_C".to_string()),
+                num_exports: Some(3),
+                export: export(0, "Owner")
+            },
+            NetFieldExports { path_name_index: 7, export: export(2, "Health"), ..Default::default() },
+            NetFieldExports { path_name_index: 8, export: export(0, "Lost"), ..Default::default() }
+        ]);
+        assert_eq!(groups.len(), 1);
+        let group = groups.for_class("Pawn_C").unwrap();
+        assert_eq!(groups.by_index(7), Some(group));
+        assert_eq!(group.exports[2].as_ref().unwrap().name, "Health");
+        assert!(group.exports[1].is_none());
+
+        // checksum bit, handle 2 with 8 bits of 0xAB, then the terminator
+        let bytes = pack_bits(&[(0, 1), (3 << 1, 8), (8 << 1, 8), (0xAB, 8), (0, 8), (0xFF, 8)]);
+        let mut slice = bytes.as_slice();
+        let mut reader = BitReader::new(&mut slice, 41);
+        let properties = replicator::read_properties(&mut reader, group, &SchemaRegistry::new()).unwrap();
+        assert_eq!(properties.len(), 1);
+        assert_eq!((properties[0].handle, properties[0].name.as_str()), (2, "Health"));
+        assert_eq!(properties[0].value, PropertyValue::Raw { num_bits: 8, data: vec![0xAB] });
+        assert_eq!(reader.pos(), 33);
+        let mut schemas = SchemaRegistry::new();
+        schemas.register("Pawn_C", "Health", PropertyType::IntPacked);
+        let mut slice = bytes.as_slice();
+        let properties = replicator::read_properties(&mut BitReader::new(&mut slice, 41), group, &schemas).unwrap();
+        assert_eq!(properties[0].value, PropertyValue::Raw { num_bits: 8, data: vec![0xAB] }); // unterminated int
+        let bytes = pack_bits(&[(0, 1), (3 << 1, 8), (16 << 1, 8), (0xAB, 8)]);
+        let mut slice = bytes.as_slice();
+        assert!(replicator::read_properties(&mut BitReader::new(&mut slice, 25), group, &schemas).is_err());
+    }
+
+    #[test]
+    fn property_values() {
+        use crate::data::schema::{PropertyType, PropertyValue, SchemaRegistry};
+        let schemas = SchemaRegistry::fortnite();
+        assert_eq!(schemas.decode("FortPlayerStateAthena", "bIsABot", vec![1], 1), PropertyValue::Bool(true));
+        assert_eq!(schemas.decode("FortPlayerStateAthena", "KillScore", vec![3, 0, 0, 0], 32), PropertyValue::Int(3));
+        assert_eq!(schemas.decode("FortPlayerStateAthena", "Unknown", vec![3], 2), PropertyValue::Raw { num_bits: 2, data: vec![3] });
+        let mut bob = Vec::new();
+        fstring(&mut bob, "Bob");
+        let name = PropertyType::String.decode(bob, 64);
+        assert_eq!(name, PropertyValue::String("Bob".to_string()));
+        assert_eq!(PropertyType::Object.decode(vec![0x0C], 8), PropertyValue::Object(NetworkGUID(6)));
+        assert_eq!(PropertyType::Float.decode(vec![0, 0, 0x80, 0x3F], 32), PropertyValue::Float(1.0));
+        assert_eq!(PropertyType::Float.decode(vec![0, 0, 0x80, 0x3F], 30), PropertyValue::Raw { num_bits: 30, data: vec![0, 0, 0x80, 0x3F] });
+        // 2 elements, element 1 changed with handle 1 holding 8 bits of 0x0A
+        let array = PropertyType::Array(Box::new(PropertyType::IntPacked)).decode(vec![4, 4, 2, 16, 20, 0, 0], 56);
+        assert_eq!(array, PropertyValue::Array { len: 2, elements: vec![(1, PropertyValue::Int(10))] });
+    }
+
+    #[test]
+    fn channel_events() {
+        use crate::data::packet::{ChannelEvent, PacketParser};
+        use crate::uetypes::ChannelCloseReason;
+        let mut parser = PacketParser::new();
+        parser.received_packet_data(&actor_channel_packet(1, &[])).unwrap();
+        assert_eq!(parser.take_channel_events(), vec![
+            ChannelEvent::Opened { index: 3, actor: NetworkGUID(3) },
+            ChannelEvent::Closed { index: 3, actor: Some(NetworkGUID(3)), reason: ChannelCloseReason::Dormancy, dormant: true }
+        ]);
+        assert!(parser.take_channel_events().is_empty());
+    }
+
+    #[test]
+    fn replay_visitor() {
+        use crate::data::net::DemoFrame;
+        use crate::data::packet::PacketParser;
+        use crate::data::packet::SpawnedActor;
+        use crate::data::visitor::ReplayVisitor;
+        use crate::uetypes::ChannelCloseReason;
+        use std::sync::{Arc, Mutex};
+        #[derive(Default)]
+        struct Log(Vec<String>);
+        impl ReplayVisitor for Log {
+            fn on_frame(&mut self, time_seconds: f32) {
+                self.0.push(format!("frame {}", time_seconds));
+            }
+            fn on_actor_spawned(&mut self, actor: &SpawnedActor) {
+                self.0.push(format!("spawned {} on {}", actor.actor.actor_net_guid.0, actor.channel));
+            }
+            fn on_channel_closed(&mut self, channel: u32, _actor: Option<NetworkGUID>, reason: ChannelCloseReason, _dormant: bool) {
+                self.0.push(format!("closed {} {:?}", channel, reason));
+            }
+            fn on_net_guid_exported(&mut self, net_guid: NetworkGUID, path: &str) {
+                self.0.push(format!("guid {} {}", net_guid.0, path));
+            }
+        }
+        fn send<T: Send>() {}
+        send::<PacketParser>();
+        let log = Arc::new(Mutex::new(Log::default()));
+        let mut parser = PacketParser::new();
+        parser.add_visitor(Box::new(log.clone()));
+        let packet = actor_channel_packet(1, &[]);
+        let frame = DemoFrame {
+            time_seconds: 2.5,
+            net_guids: vec![(NetworkGUID(4), "Pawn_C".to_string())],
+            packets: vec![packet.as_slice()],
+            ..Default::default()
+        };
+        parser.received_frame(&frame).unwrap();
+        assert_eq!(log.lock().unwrap().0, vec!["frame 2.5", "guid 4 Pawn_C", "spawned 3 on 3", "closed 3 Dormancy"]);
+    }
+
+    #[test]
+    fn rpcs() {
+        use crate::data::net::NetFieldExportGroup;
+        use crate::data::packet::PacketParser;
+        use crate::data::replicator::{self, NetFieldExportGroupMap, Rpc};
+        use crate::data::schema::{PropertyType, PropertyValue, SchemaRegistry};
+        let mut groups = NetFieldExportGroupMap::default();
+        groups.add_group(NetFieldExportGroup {
+            path_name: "Pawn_C_ClassNetCache".to_string(),
+            path_name_index: 2,
+            exports: vec![export(0, "ServerEmote"), export(1, "Multicast_Damage"), export(2, "Inventory")]
+        });
+        groups.add_group(NetFieldExportGroup {
+            path_name: "/Game/Pawn.Pawn_C:Multicast_Damage".to_string(),
+            path_name_index: 3,
+            exports: vec![export(0, "Damage")]
+        });
+        groups.add_group(NetFieldExportGroup {
+            path_name: "/Game/Car.Car_C:Multicast_Damage".to_string(),
+            path_name_index: 4,
+            exports: vec![]
+        });
+        let mut schemas = SchemaRegistry::new();
+        schemas.register("Pawn_C:Multicast_Damage", "Damage", PropertyType::IntPacked);
+        let cache = groups.for_class("Pawn_C_ClassNetCache").unwrap();
+        assert_eq!(groups.for_function("Pawn_C", "Multicast_Damage").unwrap().path_name_index, 3);
+        assert_eq!(groups.for_function("Car_C", "Multicast_Damage").unwrap().path_name_index, 4);
+        let mut bytes: &[u8] = &pack_bits(&[
+            (1, 1), (48, 8), // Multicast_Damage with 24 bits of parameters
+            (2, 8), (16, 8), (40, 8), // Damage of 20
+            (2, 2), (2, 8), (1, 1), // Inventory, not a function
+            (0, 2), (0, 8) // ServerEmote without parameters
+        ]);
+        let mut reader = BitReader::new(&mut bytes, 54);
+        let rpcs = replicator::read_rpcs(&mut reader, 3, NetworkGUID(6), cache, &groups, &schemas).unwrap();
+        assert_eq!(rpcs.len(), 2);
+        assert_eq!((rpcs[0].actor, rpcs[0].function_name.as_str()), (NetworkGUID(6), "Multicast_Damage"));
+        assert_eq!(rpcs[0].params[0].value, PropertyValue::Int(20));
+        assert_eq!(rpcs[1], Rpc { channel: 3, actor: NetworkGUID(6), function_name: "ServerEmote".to_string(), params: vec![] });
+
+        // function declared by a parent class
+        groups.add_group(NetFieldExportGroup {
+            path_name: "Hero_C_ClassNetCache".to_string(),
+            path_name_index: 5,
+            exports: vec![export(0, "NetMulticast_Emote")]
+        });
+        groups.add_group(NetFieldExportGroup {
+            path_name: "/Script/FortniteGame.FortPawn:NetMulticast_Emote".to_string(),
+            path_name_index: 6,
+            exports: vec![export(0, "EmoteId")]
+        });
+        schemas.register("FortPawn:NetMulticast_Emote", "EmoteId", PropertyType::IntPacked);
+        assert_eq!(groups.for_function("Hero_C", "NetMulticast_Emote").unwrap().path_name_index, 6);
+        let cache = groups.for_class("Hero_C_ClassNetCache").unwrap();
+        let mut bytes: &[u8] = &pack_bits(&[(0, 1), (48, 8), (2, 8), (16, 8), (14, 8)]);
+        let mut reader = BitReader::new(&mut bytes, 33);
+        let rpcs = replicator::read_rpcs(&mut reader, 3, NetworkGUID(6), cache, &groups, &schemas).unwrap();
+        assert_eq!(rpcs[0].function_name, "NetMulticast_Emote");
+        assert_eq!(rpcs[0].params[0].value, PropertyValue::Int(7));
+
+        // properties followed by function calls in the same payload
+        let mut parser = PacketParser::new();
+        parser.set_replication(true);
+        parser.net_guid_cache.net_guid_to_path.insert(NetworkGUID(3), "Pawn_C".to_string());
+        parser.export_groups.add_group(NetFieldExportGroup {
+            path_name: "/Game/Pawn.Pawn_C".to_string(),
+            path_name_index: 1,
+            exports: vec![export(0, "Health")]
+        });
+        parser.export_groups.add_group(NetFieldExportGroup {
+            path_name: "Pawn_C_ClassNetCache".to_string(),
+            path_name_index: 2,
+            exports: vec![export(0, "ServerEmote")]
+        });
+        parser.received_packet_data(&actor_channel_packet(1, &[
+            (0b11, 2), (42 << 1, 8),
+            (0, 1), (1 << 1, 8), (8 << 1, 8), (0xAB, 8), (0, 8), // Health
+            (0, 1), (0, 8) // ServerEmote
+        ])).unwrap();
+        assert_eq!(parser.take_replicated()[0].properties[0].name, "Health");
+        assert_eq!(parser.take_rpcs(), vec![Rpc { channel: 3, actor: NetworkGUID(3), function_name: "ServerEmote".to_string(), params: vec![] }]);
+    }
+
+    #[test]
+    fn actor_registry() {
+        use crate::data::net::DemoFrame;
+        use crate::data::packet::PacketParser;
+        let mut parser = PacketParser::new();
+        let dormant = actor_channel_packet(1, &[]);
+        let destroyed = actor_channel_packet(0, &[]);
+        parser.received_frame(&DemoFrame { time_seconds: 2.5, packets: vec![dormant.as_slice()], ..Default::default() }).unwrap();
+        let rock = parser.actors.get(&NetworkGUID(3)).unwrap();
+        assert_eq!((rock.spawn_time, rock.despawn_time, rock.channel), (2.5, None, 3));
+        assert_eq!(rock.archetype_path, None);
+        parser.net_guid_cache.net_guid_to_path.insert(NetworkGUID(3), "Rock_C".to_string());
+        parser.received_frame(&DemoFrame { time_seconds: 4.0, packets: vec![destroyed.as_slice()], ..Default::default() }).unwrap();
+        let rocks: Vec<_> = parser.actors.of_class("Rock_C").collect();
+        assert_eq!(rocks.len(), 1);
+        assert_eq!((rocks[0].spawn_time, rocks[0].despawn_time), (2.5, Some(4.0)));
+        assert_eq!(parser.actors.of_class("Tree_C").count(), 0);
+    }
+
+    #[test]
+    fn movement_timeline() {
+        use crate::data::movement::{MovementTimeline, RepMovement, RotatorQuantization, VectorQuantization};
+        use crate::data::packet::FRotator;
+        use crate::data::replicator::{PropertyUpdate, ReplicatedProperties};
+        use crate::data::schema::{PropertyType, PropertyValue};
+        use crate::data::visitor::ReplayVisitor;
+        use crate::uetypes::ChannelCloseReason;
+        let bytes = pack_bits(&[
+            (0, 2), // flags
+            (0, 5), (3, 2), (2, 2), (0, 2), // location
+            (1, 1), (16384, 16), (0, 2), // rotation
+            (0, 5), (2, 2), (2, 2), (2, 2) // velocity
+        ]);
+        let movement_type = PropertyType::RepMovement {
+            location: VectorQuantization::RoundWholeNumber,
+            rotation: RotatorQuantization::ShortComponents,
+            velocity: VectorQuantization::RoundWholeNumber
+        };
+        let movement = RepMovement {
+            location: FVector(1.0, 0.0, -2.0),
+            rotation: FRotator(90.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        assert_eq!(movement_type.decode(bytes, 43), PropertyValue::Movement(movement.clone()));
+
+        let mut timeline = MovementTimeline::new();
+        let update = ReplicatedProperties {
+            channel: 3,
+            actor: NetworkGUID(6),
+            path_name: "PlayerPawn_Athena_C".to_string(),
+            properties: vec![PropertyUpdate { handle: 1, name: "ReplicatedMovement".to_string(), value: PropertyValue::Movement(movement) }]
+        };
+        timeline.on_frame(1.0);
+        timeline.on_property_update(&update);
+        timeline.on_frame(2.0);
+        timeline.on_property_update(&update);
+        timeline.on_channel_closed(3, Some(NetworkGUID(6)), ChannelCloseReason::Destroyed, false);
+        timeline.on_frame(3.0);
+        let times: Vec<f32> = timeline.get(&NetworkGUID(6)).iter().map(|x| x.time_seconds).collect();
+        assert_eq!(times, vec![1.0, 2.0]);
+        assert_eq!(timeline.get(&NetworkGUID(6))[1].movement.location, FVector(1.0, 0.0, -2.0));
+        assert!(timeline.get(&NetworkGUID(7)).is_empty());
+    }
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use std::time::SystemTime;
 use replayskimmer::ureplay::UReplay;
-use replayskimmer::fnchunk::FNSkim;
-use replayskimmer::data::net::DemoFrame;
+use replayskimmer::fnchunk::{FNSkim, SkimDepth};
+use replayskimmer::data::OodleDecoder;
 
 fn measure(block: fn() -> replayskimmer::Result<()>) -> replayskimmer::Result<()> {
     let current_time = SystemTime::now();
@@ -13,17 +13,13 @@ fn measure(block: fn() -> replayskimmer::Result<()>) -> replayskimmer::Result<()
 fn main() -> replayskimmer::Result<()> {
     measure(|| {
         let replay= UReplay::parse(std::fs::read("season12.replay")?)?;
-        let skim = FNSkim::skim(replay, true)?;
-        //println!("{:#?}", skim);
-        //for x in skim.data_chunks.expect("") {
-        //    let mut slice: &[u8] = x.data.as_slice();
-        //    //println!("{:#?}", data::net::DemoFrame::parse(&mut slice)?);
-        //    let mut demo_frames: Vec<DemoFrame> = Vec::new();
-        //    while !slice.is_empty() {
-        //        demo_frames.push(DemoFrame::parse(&mut slice)?)
-        //    }
-        //    //println!("{:#?}", demo_frames)
-        //}
+        let skim = FNSkim::skim(replay, SkimDepth::Replication)?;
+        let mut frames = 0;
+        skim.for_each_frame(&OodleDecoder, |_| {
+            frames += 1;
+            Ok(())
+        })?;
+        println!("{} frames", frames);
         Ok(())
     })?;
     Ok(())
@@ -1,7 +1,12 @@
-use crate::ureplay::{Chunk, UReplay};
+use crate::ureplay::ReplayMeta;
 
 use serde::Deserialize;
-use crate::uetypes::{GUID};
+use crate::uetypes::{GUID, NetworkVersion, UEReadExt, BuildConfig, BuildTarget, read_custom_versions};
+use crate::data::{decode_payload, Decompressor};
+use crate::data::net::{DemoFrame, NetFieldExportGroup, NetworkGUID, StringExt};
+use crate::data::packet::PacketParser;
+use byteorder::{ReadBytesExt, LE};
+use crate::ResultExt;
 use serde::export::fmt::Debug;
 use serde::export::Formatter;
 use std::io::Read;
@@ -12,31 +17,127 @@ use aes_soft::{Aes128, Aes256};
 use block_modes::block_padding::Pkcs7;
 use bincode::ErrorKind;
 
-#[derive(Debug, Deserialize, Default, PartialEq)]
+/// Magic number at the start of header chunks.
+pub const NETWORK_DEMO_MAGIC: u32 = 0x2CF5A13D;
+
+/// Flags of the demo header (`EReplayHeaderFlags`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayHeaderFlags(pub u32);
+
+impl ReplayHeaderFlags {
+    pub const CLIENT_RECORDED: Self = ReplayHeaderFlags(1);
+    pub const HAS_STREAMING_FIXES: Self = ReplayHeaderFlags(2);
+    pub const DELTA_CHECKPOINTS: Self = ReplayHeaderFlags(4);
+    pub const GAME_SPECIFIC_FRAME_DATA: Self = ReplayHeaderFlags(8);
+    pub const REPLAY_CONNECTION: Self = ReplayHeaderFlags(16);
+    pub const ACTOR_PRIORITIZATION_ENABLED: Self = ReplayHeaderFlags(32);
+    pub const NET_RELEVANCY_ENABLED: Self = ReplayHeaderFlags(64);
+    pub const ASYNC_RECORDED: Self = ReplayHeaderFlags(128);
+
+    /// Whether all bits of `flags` are set.
+    pub fn contains(self, flags: ReplayHeaderFlags) -> bool {
+        (self.0 & flags.0) == flags.0
+    }
+}
+
+impl std::ops::BitOr for ReplayHeaderFlags {
+    type Output = ReplayHeaderFlags;
+
+    fn bitor(self, rhs: ReplayHeaderFlags) -> ReplayHeaderFlags {
+        ReplayHeaderFlags(self.0 | rhs.0)
+    }
+}
+
+/// Demo header, fields missing from older network versions are left at their defaults.
+#[derive(Debug, Default, PartialEq)]
 pub struct HeaderChunk {
     pub network_magic: u32,
     pub network_version: u32,
+    pub custom_versions: Vec<(GUID, i32)>,
     pub network_checksum: u32,
     pub engine_network_version: u32,
     pub game_network_protocol_version: u32,
     pub id: GUID,
+    pub package_version_ue4: i32,
+    pub package_version_licensee_ue4: i32,
     pub major: u16,
     pub minor: u16,
     pub patch: u16,
     pub changelist: u32,
     pub branch_data: String,
     pub level_names_and_times: Vec<(String, u32)>,
-    pub flags: u32,
-    pub game_specific_data: Vec<String>
+    pub flags: ReplayHeaderFlags,
+    pub game_specific_data: Vec<String>,
+    pub min_record_hz: f32,
+    pub max_record_hz: f32,
+    pub frame_limit_in_ms: f32,
+    pub checkpoint_limit_in_ms: f32,
+    pub platform: String,
+    pub build_config: BuildConfig,
+    pub build_target: BuildTarget
 }
 
 impl HeaderChunk {
-    pub fn parse(chunk: Chunk) -> crate::Result<HeaderChunk> {
-        if chunk.variant != 0 {
-            //panic!("tried to parse another chunk as header chunk")
-            return Err(crate::ErrorKind::ReplayParseError("tried to parse another chunk as header chunk".to_string()).into());
+    /// Parses the payload of a header chunk in the layout of its network version.
+    pub fn from_bytes(data: &[u8]) -> crate::Result<HeaderChunk> {
+        let mut cursor = data;
+        let mut header = HeaderChunk {
+            network_magic: cursor.read_u32::<LE>()?,
+            ..Default::default()
+        };
+        if header.network_magic != NETWORK_DEMO_MAGIC {
+            return Err(crate::ErrorKind::ReplayParseError(format!("invalid header magic {:#x}", header.network_magic)).into());
+        }
+        header.network_version = cursor.read_u32::<LE>()?;
+        if header.has_network_version(NetworkVersion::UseCustomVersion) {
+            header.custom_versions = read_custom_versions(&mut cursor)?;
+        }
+        header.network_checksum = cursor.read_u32::<LE>()?;
+        header.engine_network_version = cursor.read_u32::<LE>()?;
+        header.game_network_protocol_version = cursor.read_u32::<LE>()?;
+        if header.has_network_version(NetworkVersion::HeaderGuid) {
+            header.id = bincode::deserialize_from(&mut cursor)?;
+        }
+        if header.has_network_version(NetworkVersion::SavePackageVersionUE) {
+            header.package_version_ue4 = cursor.read_i32::<LE>()?;
+            header.package_version_licensee_ue4 = cursor.read_i32::<LE>()?;
+        }
+        if header.has_network_version(NetworkVersion::SaveFullEngineVersion) {
+            header.major = cursor.read_u16::<LE>()?;
+            header.minor = cursor.read_u16::<LE>()?;
+            header.patch = cursor.read_u16::<LE>()?;
+            header.changelist = cursor.read_u32::<LE>()?;
+            header.branch_data = cursor.read_fstring()?;
+        } else {
+            header.changelist = cursor.read_u32::<LE>()?;
         }
-        Ok(bincode::deserialize::<HeaderChunk>(chunk.data.as_slice())?)
+        if header.has_network_version(NetworkVersion::MultipleLevelsTimeChanges) {
+            header.level_names_and_times = bincode::deserialize_from(&mut cursor)?;
+        } else if header.has_network_version(NetworkVersion::MultipleLevels) {
+            let names: Vec<String> = bincode::deserialize_from(&mut cursor)?;
+            header.level_names_and_times = names.into_iter().map(|x| (x, 0)).collect();
+        } else {
+            header.level_names_and_times.push((cursor.read_fstring()?, 0));
+        }
+        if header.has_network_version(NetworkVersion::HeaderFlags) {
+            header.flags = ReplayHeaderFlags(cursor.read_u32::<LE>()?);
+        }
+        header.game_specific_data = bincode::deserialize_from(&mut cursor)?;
+        if header.has_network_version(NetworkVersion::RecordingMetadata) {
+            header.min_record_hz = cursor.read_f32::<LE>()?;
+            header.max_record_hz = cursor.read_f32::<LE>()?;
+            header.frame_limit_in_ms = cursor.read_f32::<LE>()?;
+            header.checkpoint_limit_in_ms = cursor.read_f32::<LE>()?;
+            header.platform = cursor.read_fstring()?;
+            header.build_config = BuildConfig::parse(cursor.read_u8()?);
+            header.build_target = BuildTarget::parse(cursor.read_u8()?);
+        }
+        Ok(header)
+    }
+
+    /// Whether the replay was recorded with at least network version `version`.
+    pub fn has_network_version(&self, version: NetworkVersion) -> bool {
+        self.network_version >= version as u32
     }
 }
 
@@ -53,14 +154,32 @@ pub struct EventChunk {
 
 pub type Aes = Ecb<Aes256, Pkcs7>;
 
+fn cipher(key: &[u8]) -> crate::Result<Aes> {
+    if key.len() != 32 {
+        return Err(crate::ErrorKind::DecryptionError(format!("expected a 32 byte key, got {} bytes", key.len())).into());
+    }
+    Ok(Aes::new_var(key, Default::default())?)
+}
+
+/// Decrypts chunk data with `enc_key`, data of unencrypted replays is returned as is.
+pub fn decrypt(data: Vec<u8>, enc_key: Option<&[u8]>) -> crate::Result<Vec<u8>> {
+    match enc_key {
+        Some(key) => Ok(cipher(key)?.decrypt_vec(data.as_slice())
+            .chain_err(|| crate::ErrorKind::DecryptionError("wrong key or corrupted chunk".to_string()))?),
+        None => Ok(data)
+    }
+}
+
+/// Encrypts chunk data with `enc_key`, the reverse of `decrypt`.
+pub fn encrypt(data: &[u8], enc_key: &[u8]) -> crate::Result<Vec<u8>> {
+    Ok(cipher(enc_key)?.encrypt_vec(data))
+}
+
 impl EventChunk {
-    pub fn parse(c: Chunk, enc_key: &[u8]) -> crate::Result<EventChunk> {
-        if c.variant != 3 {
-            return Err(crate::ErrorKind::ReplayParseError("tried to parse another chunk as event chunk".to_string()).into());
-        }
-        let mut event_chunk = bincode::deserialize::<EventChunk>(c.data.as_slice())?;
-        let cipher = Aes::new_var(enc_key, Default::default())?;
-        event_chunk.data = cipher.decrypt_vec(event_chunk.data.as_slice())?;
+    /// Parses the payload of an event chunk and decrypts its data if `enc_key` is given.
+    pub fn from_bytes(data: &[u8], enc_key: Option<&[u8]>) -> crate::Result<EventChunk> {
+        let mut event_chunk = bincode::deserialize::<EventChunk>(data)?;
+        event_chunk.data = decrypt(event_chunk.data, enc_key)?;
         Ok(event_chunk)
     }
 }
@@ -69,4 +188,94 @@ impl Debug for EventChunk {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         f.write_str(&*format!("event chunk in group {}", self.group))
     }
-}
\ No newline at end of file
+}
+#[derive(Deserialize, PartialEq)]
+pub struct CheckpointChunk {
+    pub id: String,
+    pub group: String,
+    pub metadata: String,
+    pub start_time: u32,
+    pub end_time: u32,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8> // decrypted and decompressed once parsed
+}
+
+/// Replay state saved in a checkpoint.
+#[derive(Debug, Default)]
+pub struct CheckpointState {
+    pub packet_offset: Option<u64>,
+    pub level_index: Option<i32>,
+    pub deleted_startup_actors: Vec<String>,
+    pub export_groups: Vec<NetFieldExportGroup>,
+    pub frame: DemoFrame
+}
+
+impl CheckpointChunk {
+    /// Parses the payload of a checkpoint chunk, decrypting and decompressing its data.
+    pub fn from_bytes(data: &[u8], meta: &ReplayMeta, decompressor: &dyn Decompressor) -> crate::Result<CheckpointChunk> {
+        let mut checkpoint = bincode::deserialize::<CheckpointChunk>(data)?;
+        checkpoint.data = decode_payload(checkpoint.data, meta, decompressor)?;
+        Ok(checkpoint)
+    }
+
+    /// Reads the saved replay state, loading the net guid cache and export groups into
+    /// `packet_parser` and feeding it the packets of the checkpoint frame.
+    pub fn read_state(&self, header: &HeaderChunk, packet_parser: &mut PacketParser) -> crate::Result<CheckpointState> {
+        if header.flags.contains(ReplayHeaderFlags::DELTA_CHECKPOINTS) {
+            return Err(crate::ErrorKind::ReplayParseError("delta checkpoints are not supported".to_string()).into());
+        }
+        let mut cursor = self.data.as_slice();
+        let mut state = CheckpointState::default();
+        if header.flags.contains(ReplayHeaderFlags::HAS_STREAMING_FIXES) {
+            state.packet_offset = Some(cursor.read_u64::<LE>()?);
+        }
+        if header.has_network_version(NetworkVersion::MultipleLevels) {
+            state.level_index = Some(cursor.read_i32::<LE>()?);
+        }
+        if header.has_network_version(NetworkVersion::DeletedStartupActors) {
+            let num_deleted = cursor.read_i32::<LE>()?;
+            for _ in 0..num_deleted {
+                state.deleted_startup_actors.push(cursor.read_fstring()?);
+            }
+        }
+        let mut name_table: Vec<String> = Vec::new();
+        let num_guids = cursor.read_i32::<LE>()?;
+        for _ in 0..num_guids {
+            let guid = NetworkGUID(cursor.read_int_packed()?);
+            cursor.read_int_packed()?; // outer guid
+            let path = if header.has_network_version(NetworkVersion::GuidNameTable) {
+                if cursor.read_u8()? != 0 {
+                    let path = cursor.read_fstring()?;
+                    name_table.push(path.clone());
+                    path
+                } else {
+                    let index = cursor.read_int_packed()? as usize;
+                    name_table.get(index).cloned()
+                        .ok_or_else(|| crate::ErrorKind::ReplayParseError(format!("missing checkpoint path name {}", index)))?
+                }
+            } else {
+                cursor.read_fstring()?
+            };
+            if !header.has_network_version(NetworkVersion::GuidCacheChecksums) {
+                cursor.read_u32::<LE>()?; // checksum
+            }
+            cursor.read_u8()?; // flags
+            packet_parser.net_guid_cache.net_guid_to_path.insert(guid, path.remove_all_path_prefixes());
+        }
+        let num_groups = cursor.read_u32::<LE>()?;
+        for _ in 0..num_groups {
+            let group = NetFieldExportGroup::parse(&mut cursor)?;
+            packet_parser.export_groups.add_group(group.clone());
+            state.export_groups.push(group);
+        }
+        state.frame = DemoFrame::parse(&mut cursor, header)?;
+        packet_parser.received_frame(&state.frame)?;
+        Ok(state)
+    }
+}
+
+impl Debug for CheckpointChunk {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(&format!("checkpoint chunk {} with size of {}", self.id, self.data.len()))
+    }
+}
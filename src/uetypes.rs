@@ -11,9 +11,16 @@ use byteorder::{ReadBytesExt, LE};
 use crate::ErrorKind;
 use bincode::Options;
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct GUID(String);
 
+impl GUID {
+    /// The 16 bytes the guid was read from.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        hex::decode(&self.0).unwrap_or_default()
+    }
+}
+
 struct GUIDVisitor;
 impl<'de> Visitor<'de> for GUIDVisitor {
     type Value = GUID;
@@ -299,6 +306,90 @@ impl ChannelCloseReason {
     }
 }
 
+/// Local file replay versions (`EReplayHeaderVersion`), stored as `ReplayMeta::file_version`.
+#[repr(u32)]
+#[derive(Debug, EnumIter, Copy, Clone, AsStaticStr, PartialEq, PartialOrd)]
+pub enum FileVersion {
+    Initial = 0,
+    /// Friendly names are padded with spaces to a fixed size.
+    FixedSizeFriendlyName = 1,
+    /// Adds `is_compressed`.
+    Compression = 2,
+    /// Adds `timestamp`.
+    RecordedTimestamp = 3,
+    /// Data chunks carry their start and end times.
+    StreamChunkTimes = 4,
+    /// Friendly names may be stored as UTF-16.
+    FriendlyNameEncoding = 5,
+    /// Adds `is_encrypted` and `encryption_key`, data chunks carry their decrypted size.
+    Encryption = 6,
+    /// Adds `custom_versions` after the file version.
+    CustomVersions = 7
+}
+
+impl FileVersion {
+    pub(crate) fn parse(id: u32) -> Option<Self> {
+        Self::iter().find(|x| (*x as u32) == id)
+    }
+}
+
+/// Build configuration (`EBuildConfiguration`) a replay was recorded with.
+#[repr(u8)]
+#[derive(Debug, Default, EnumIter, Copy, Clone, AsStaticStr, PartialEq)]
+pub enum BuildConfig { #[default] Unknown, Debug, DebugGame, Development, Shipping, Test }
+
+impl BuildConfig {
+    pub(crate) fn parse(id: u8) -> Self {
+        Self::iter().find(|x| (*x as u8) == id).unwrap_or(BuildConfig::Unknown)
+    }
+}
+
+/// Target type (`EBuildTargetType`) a replay was recorded with.
+#[repr(u8)]
+#[derive(Debug, Default, EnumIter, Copy, Clone, AsStaticStr, PartialEq)]
+pub enum BuildTarget { #[default] Unknown, Game, Server, Client, Editor, Program }
+
+impl BuildTarget {
+    pub(crate) fn parse(id: u8) -> Self {
+        Self::iter().find(|x| (*x as u8) == id).unwrap_or(BuildTarget::Unknown)
+    }
+}
+
+/// Reads a custom version container, an i32 count of (guid, version) pairs.
+pub(crate) fn read_custom_versions<R: Read>(mut reader: R) -> crate::Result<Vec<(GUID, i32)>> {
+    let num_versions = reader.read_i32::<LE>()?;
+    let mut versions = Vec::new();
+    for _ in 0..num_versions {
+        versions.push((bincode::deserialize_from(&mut reader)?, reader.read_i32::<LE>()?));
+    }
+    Ok(versions)
+}
+
+/// Demo network versions (`ENetworkVersionHistory`), stored as `HeaderChunk::network_version`.
+#[repr(u32)]
+#[derive(Debug, EnumIter, Copy, Clone, AsStaticStr, PartialEq, PartialOrd)]
+pub enum NetworkVersion {
+    ReplayInitial = 1,
+    SaveAbsTimeMs = 2,
+    IncreaseBuffer = 3,
+    SaveEngineVersion = 4,
+    ExtraVersion = 5,
+    MultipleLevels = 6,
+    MultipleLevelsTimeChanges = 7,
+    DeletedStartupActors = 8,
+    HeaderFlags = 9,
+    LevelStreamingFixes = 10,
+    SaveFullEngineVersion = 11,
+    HeaderGuid = 12,
+    CharacterMovement = 13,
+    CharacterMovementNoInterp = 14,
+    GuidNameTable = 15,
+    GuidCacheChecksums = 16,
+    SavePackageVersionUE = 17,
+    RecordingMetadata = 18,
+    UseCustomVersion = 19
+}
+
 pub trait UEReadExt: Read {
     fn read_fstring(&mut self) -> crate::Result<String>;
     fn read_fname(&mut self) -> crate::Result<String>;
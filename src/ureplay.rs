@@ -1,10 +1,25 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde::export::fmt::Debug;
 use serde::export::Formatter;
+use std::io::{Read, Write, Seek, SeekFrom, Cursor};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use crate::uetypes::{FileVersion, GUID, UEReadExt, read_custom_versions};
+use crate::ResultExt;
+use crate::uchunk::{HeaderChunk, EventChunk, CheckpointChunk, decrypt, encrypt};
+use crate::data::{DataChunk, Decompressor};
 
-#[derive(Debug, Deserialize, PartialEq)]
+/// Magic number at the start of local file replays.
+pub const FILE_MAGIC: u32 = 0x1CA2E27F;
+
+/// Friendly names are padded with spaces to this many characters since `FixedSizeFriendlyName`.
+const FRIENDLY_NAME_SIZE: usize = 256;
+
+/// Replay info following the magic, fields missing from older file versions are left at their
+/// defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ReplayMeta {
     pub file_version: u32,
+    pub custom_versions: Vec<(GUID, i32)>,
     pub length_in_ms: u32,
     pub network_version: u32,
     pub changelist: u32,
@@ -13,38 +28,418 @@ pub struct ReplayMeta {
     pub timestamp: u64,
     pub is_compressed: bool,
     pub is_encrypted: bool,
-    #[serde(with = "serde_bytes")]
     pub encryption_key: Vec<u8>
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+impl ReplayMeta {
+    /// Reads the replay info for the file version found in the stream.
+    pub fn parse<R: Read>(mut reader: R) -> crate::Result<ReplayMeta> {
+        let mut meta = ReplayMeta {
+            file_version: reader.read_u32::<LE>()?,
+            ..Default::default()
+        };
+        if FileVersion::parse(meta.file_version).is_none() {
+            return Err(crate::ErrorKind::ReplayParseError(format!("unsupported replay file version {}, latest supported is {}",
+                                                                  meta.file_version, FileVersion::CustomVersions as u32)).into());
+        }
+        if meta.has_file_version(FileVersion::CustomVersions) {
+            meta.custom_versions = read_custom_versions(&mut reader)?;
+        }
+        meta.length_in_ms = reader.read_u32::<LE>()?;
+        meta.network_version = reader.read_u32::<LE>()?;
+        meta.changelist = reader.read_u32::<LE>()?;
+        meta.friendly_name = reader.read_fstring()?;
+        meta.is_live = bincode::deserialize_from(&mut reader)?;
+        if meta.has_file_version(FileVersion::RecordedTimestamp) {
+            meta.timestamp = reader.read_u64::<LE>()?;
+        }
+        if meta.has_file_version(FileVersion::Compression) {
+            meta.is_compressed = bincode::deserialize_from(&mut reader)?;
+        }
+        if meta.has_file_version(FileVersion::Encryption) {
+            meta.is_encrypted = bincode::deserialize_from(&mut reader)?;
+            let key_len = reader.read_u32::<LE>()?;
+            meta.encryption_key = vec![0u8; key_len as usize];
+            reader.read_exact(meta.encryption_key.as_mut_slice())?;
+        }
+        Ok(meta)
+    }
+
+    /// Writes the replay info in the layout of its file version.
+    pub fn write<W: Write>(&self, mut writer: W) -> crate::Result<()> {
+        writer.write_u32::<LE>(self.file_version)?;
+        if self.has_file_version(FileVersion::CustomVersions) {
+            writer.write_i32::<LE>(self.custom_versions.len() as i32)?;
+            for (guid, version) in &self.custom_versions {
+                writer.write_all(guid.to_bytes().as_slice())?;
+                writer.write_i32::<LE>(*version)?;
+            }
+        }
+        writer.write_u32::<LE>(self.length_in_ms)?;
+        writer.write_u32::<LE>(self.network_version)?;
+        writer.write_u32::<LE>(self.changelist)?;
+        let mut friendly_name = self.friendly_name.clone();
+        if self.has_file_version(FileVersion::FixedSizeFriendlyName) {
+            let len = friendly_name.chars().count();
+            friendly_name.extend(std::iter::repeat_n(' ', FRIENDLY_NAME_SIZE.saturating_sub(len)));
+        }
+        bincode::serialize_into(&mut writer, &friendly_name)?;
+        bincode::serialize_into(&mut writer, &self.is_live)?;
+        if self.has_file_version(FileVersion::RecordedTimestamp) {
+            writer.write_u64::<LE>(self.timestamp)?;
+        }
+        if self.has_file_version(FileVersion::Compression) {
+            bincode::serialize_into(&mut writer, &self.is_compressed)?;
+        }
+        if self.has_file_version(FileVersion::Encryption) {
+            bincode::serialize_into(&mut writer, &self.is_encrypted)?;
+            writer.write_u32::<LE>(self.encryption_key.len() as u32)?;
+            writer.write_all(self.encryption_key.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Whether the replay was written with at least file version `version`.
+    pub fn has_file_version(&self, version: FileVersion) -> bool {
+        self.file_version >= version as u32
+    }
+
+    /// Key to decrypt chunks with, `None` if the replay is not encrypted.
+    pub fn decryption_key(&self) -> crate::Result<Option<&[u8]>> {
+        if !self.is_encrypted {
+            return Ok(None);
+        }
+        if self.encryption_key.is_empty() {
+            return Err(crate::ErrorKind::DecryptionError("replay is encrypted but has no key, supply one with UReplay::set_encryption_key".to_string()).into());
+        }
+        Ok(Some(self.encryption_key.as_slice()))
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct UReplay {
     pub file_magic: u32,
     pub meta: ReplayMeta,
-    #[serde(skip_deserializing)]
-    pub chunks: Vec<Chunk>
+    pub chunks: Vec<Chunk>,
+    /// Location of each entry in `chunks`.
+    pub index: Vec<ChunkInfo>
 }
 
-#[derive(Deserialize, PartialEq)]
+#[derive(Deserialize, Serialize, PartialEq)]
 pub struct Chunk {
     pub variant: u32,
     #[serde(with = "serde_bytes")]
     pub data: Vec<u8>
 }
 
+/// Kind of a replay chunk, stored as `Chunk::variant`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChunkType {
+    Header,
+    ReplayData,
+    Checkpoint,
+    Event,
+    Unknown(u32)
+}
+
+impl From<u32> for ChunkType {
+    fn from(variant: u32) -> Self {
+        match variant {
+            0 => ChunkType::Header,
+            1 => ChunkType::ReplayData,
+            2 => ChunkType::Checkpoint,
+            3 => ChunkType::Event,
+            x => ChunkType::Unknown(x)
+        }
+    }
+}
+
+impl From<ChunkType> for u32 {
+    fn from(chunk_type: ChunkType) -> Self {
+        match chunk_type {
+            ChunkType::Header => 0,
+            ChunkType::ReplayData => 1,
+            ChunkType::Checkpoint => 2,
+            ChunkType::Event => 3,
+            ChunkType::Unknown(x) => x
+        }
+    }
+}
+
+/// Decoded contents of a chunk.
+#[derive(Debug)]
+pub enum ReplayChunk {
+    Header(HeaderChunk),
+    Data(DataChunk),
+    Checkpoint(CheckpointChunk),
+    Event(EventChunk),
+    Unknown(Chunk)
+}
+
+impl Chunk {
+    pub fn chunk_type(&self) -> ChunkType {
+        self.variant.into()
+    }
+
+    /// Decodes the chunk according to its type, decrypting with the replay's key if it is encrypted.
+    pub fn decode(self, meta: &ReplayMeta, decompressor: &dyn Decompressor) -> crate::Result<ReplayChunk> {
+        Ok(match self.chunk_type() {
+            ChunkType::Header => ReplayChunk::Header(HeaderChunk::from_bytes(&self.data)?),
+            ChunkType::ReplayData => ReplayChunk::Data(DataChunk::from_bytes(&self.data, meta, decompressor)?),
+            ChunkType::Checkpoint => ReplayChunk::Checkpoint(CheckpointChunk::from_bytes(&self.data, meta, decompressor)?),
+            ChunkType::Event => ReplayChunk::Event(EventChunk::from_bytes(&self.data, meta.decryption_key()?)?),
+            ChunkType::Unknown(_) => ReplayChunk::Unknown(self)
+        })
+    }
+}
+
+impl Chunk {
+    /// Replaces the encrypted part of data, checkpoint and event chunks with `f(part)`, leaving
+    /// the rest of the chunk untouched. Data chunks are read in the layout of `file_version`.
+    fn map_encrypted<F: Fn(Vec<u8>) -> crate::Result<Vec<u8>>>(&mut self, file_version: u32, f: F) -> crate::Result<()> {
+        let start = match self.chunk_type() {
+            ChunkType::ReplayData if file_version >= FileVersion::StreamChunkTimes as u32 => 8, // start, end
+            ChunkType::ReplayData => {
+                // no header, the whole chunk is the payload
+                self.data = f(std::mem::take(&mut self.data))?;
+                return Ok(());
+            }
+            ChunkType::Checkpoint | ChunkType::Event => {
+                let mut cursor = self.data.as_slice();
+                for _ in 0..3 {
+                    cursor.read_fstring()?; // id, group, metadata
+                }
+                self.data.len() - cursor.len() + 8 // start and end time
+            }
+            _ => return Ok(())
+        };
+        let mut cursor = &self.data[start..];
+        let len = cursor.read_u32::<LE>()? as usize;
+        let skip = match self.chunk_type() {
+            ChunkType::ReplayData if file_version >= FileVersion::Encryption as u32 => 4, // memory size
+            _ => 0
+        };
+        let body = start + 4 + skip;
+        if body + len > self.data.len() {
+            return Err(crate::ErrorKind::ReplayParseError("encrypted data runs past the end of the chunk".to_string()).into());
+        }
+        let mapped = f(self.data[body..body + len].to_vec())?;
+        let mut data = self.data[..start].to_vec();
+        data.write_u32::<LE>(mapped.len() as u32)?;
+        data.extend_from_slice(&self.data[start + 4..body]);
+        data.extend_from_slice(mapped.as_slice());
+        data.extend_from_slice(&self.data[body + len..]);
+        self.data = data;
+        Ok(())
+    }
+}
+
 impl Debug for Chunk {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         f.write_str(&*format!("chunk type {}", self.variant))
     }
 }
 
+/// Position of a chunk in the replay file, `offset` points at the chunk payload. Data, checkpoint
+/// and event chunks also carry their start and end times in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkInfo {
+    pub variant: u32,
+    pub size: u32,
+    pub offset: u64,
+    pub start_time: Option<u32>,
+    pub end_time: Option<u32>
+}
+
+impl ChunkInfo {
+    pub fn chunk_type(&self) -> ChunkType {
+        self.variant.into()
+    }
+
+    /// Whether the chunk covers any part of `start_time..end_time`.
+    pub fn overlaps(&self, start_time: u32, end_time: u32) -> bool {
+        match (self.start_time, self.end_time) {
+            (Some(start), Some(end)) => start < end_time && end >= start_time,
+            _ => false
+        }
+    }
+}
+
+/// Reads the replay header and then the chunk headers one at a time, payloads are only read
+/// through `read_chunk`.
+pub struct ReplayReader<R: Read + Seek> {
+    reader: R,
+    pub file_magic: u32,
+    pub meta: ReplayMeta,
+    position: u64,
+    len: u64
+}
+
+impl<R: Read + Seek> ReplayReader<R> {
+    /// Reads the next chunk header and skips over its payload.
+    pub fn next_chunk(&mut self) -> crate::Result<Option<ChunkInfo>> {
+        if self.position >= self.len {
+            return Ok(None);
+        }
+        self.reader.seek(SeekFrom::Start(self.position))?;
+        let variant = self.reader.read_u32::<LE>()?;
+        let size = self.reader.read_u32::<LE>()?;
+        let offset = self.position + 8;
+        if offset + size as u64 > self.len {
+            return Err(crate::ErrorKind::ReplayParseError(format!("chunk at {} runs past the end of the file", self.position)).into());
+        }
+        // chunks whose times cannot be read are still listed, decoding them reports the error
+        let (start_time, end_time) = self.read_times(variant, size).unwrap_or((None, None));
+        self.position = offset + size as u64;
+        Ok(Some(ChunkInfo { variant, size, offset, start_time, end_time }))
+    }
+
+    fn read_times(&mut self, variant: u32, size: u32) -> crate::Result<(Option<u32>, Option<u32>)> {
+        let mut payload = (&mut self.reader).take(size as u64);
+        match ChunkType::from(variant) {
+            ChunkType::ReplayData if self.meta.has_file_version(FileVersion::StreamChunkTimes) =>
+                Ok((Some(payload.read_u32::<LE>()?), Some(payload.read_u32::<LE>()?))),
+            ChunkType::Checkpoint | ChunkType::Event => {
+                for _ in 0..3 {
+                    payload.read_fstring()?; // id, group, metadata
+                }
+                Ok((Some(payload.read_u32::<LE>()?), Some(payload.read_u32::<LE>()?)))
+            }
+            _ => Ok((None, None))
+        }
+    }
+
+    /// Loads the payload of a chunk returned by `next_chunk`.
+    pub fn read_chunk(&mut self, info: &ChunkInfo) -> crate::Result<Chunk> {
+        self.reader.seek(SeekFrom::Start(info.offset))?;
+        let mut data = vec![0u8; info.size as usize];
+        self.reader.read_exact(data.as_mut_slice())?;
+        Ok(Chunk {
+            variant: info.variant,
+            data
+        })
+    }
+
+    /// Reads the remaining chunks into a `UReplay`.
+    pub fn into_replay(mut self) -> crate::Result<UReplay> {
+        let mut chunks = Vec::new();
+        let mut index = Vec::new();
+        while let Some(info) = self.next_chunk()? {
+            chunks.push(self.read_chunk(&info)?);
+            index.push(info);
+        }
+        Ok(UReplay {
+            file_magic: self.file_magic,
+            meta: self.meta,
+            chunks,
+            index
+        })
+    }
+}
+
+impl<R: Read + Seek> Iterator for ReplayReader<R> {
+    type Item = crate::Result<ChunkInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_chunk();
+        if next.is_err() {
+            // stop after the first broken chunk header
+            self.position = self.len;
+        }
+        next.transpose()
+    }
+}
+
 impl UReplay {
     pub fn parse(bytes: Vec<u8>) -> crate::Result<UReplay> {
-        let mut slice = bytes.as_slice();
-        let mut replay: UReplay = bincode::deserialize_from(&mut slice)?;
-        while !slice.is_empty() {
-            replay.chunks.push(bincode::deserialize_from(&mut slice)?);
+        Self::from_reader(Cursor::new(bytes))?.into_replay()
+    }
+
+    /// Replaces the key used to decrypt chunks, for replays whose key was stripped.
+    pub fn set_encryption_key(&mut self, key: Vec<u8>) {
+        self.meta.encryption_key = key;
+    }
+
+    /// Decrypts data, checkpoint and event chunks in place and clears `ReplayMeta::is_encrypted`.
+    pub fn decrypt(&mut self) -> crate::Result<()> {
+        let key = match self.meta.decryption_key()? {
+            Some(key) => key.to_vec(),
+            None => return Ok(())
+        };
+        self.map_encrypted(|data| decrypt(data, Some(key.as_slice())))?;
+        self.meta.is_encrypted = false;
+        Ok(())
+    }
+
+    /// Encrypts data, checkpoint and event chunks of a decrypted replay in place with `key`, the
+    /// file version must store the encryption flag and key.
+    pub fn encrypt(&mut self, key: Vec<u8>) -> crate::Result<()> {
+        if !self.meta.has_file_version(FileVersion::Encryption) {
+            return Err(crate::ErrorKind::DecryptionError(format!("file version {} does not support encryption", self.meta.file_version)).into());
+        }
+        if self.meta.is_encrypted {
+            return Err(crate::ErrorKind::DecryptionError("replay is already encrypted".to_string()).into());
+        }
+        self.map_encrypted(|data| encrypt(data.as_slice(), key.as_slice()))?;
+        self.meta.is_encrypted = true;
+        self.meta.encryption_key = key;
+        Ok(())
+    }
+
+    /// Applies `Chunk::map_encrypted` to every chunk, keeping the index in sync.
+    fn map_encrypted<F: Fn(Vec<u8>) -> crate::Result<Vec<u8>>>(&mut self, f: F) -> crate::Result<()> {
+        let mut shift = 0i64;
+        for (i, chunk) in self.chunks.iter_mut().enumerate() {
+            let size = chunk.data.len() as i64;
+            chunk.map_encrypted(self.meta.file_version, &f)
+                .chain_err(|| crate::ErrorKind::ReplayParseError(format!("failed to process chunk {}", i)))?;
+            if let Some(info) = self.index.get_mut(i) {
+                info.offset = (info.offset as i64 + shift) as u64;
+                info.size = chunk.data.len() as u32;
+            }
+            shift += chunk.data.len() as i64 - size;
+        }
+        Ok(())
+    }
+
+    /// Writes the replay in the format read by `parse`.
+    pub fn write<W: Write>(&self, mut writer: W) -> crate::Result<()> {
+        writer.write_u32::<LE>(self.file_magic)?;
+        self.meta.write(&mut writer)?;
+        for chunk in &self.chunks {
+            bincode::serialize_into(&mut writer, chunk)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Chunks covering any part of `start_time..end_time` in milliseconds.
+    pub fn chunks_between(&self, start_time: u32, end_time: u32) -> Vec<&ChunkInfo> {
+        self.index.iter().filter(|x| x.overlaps(start_time, end_time)).collect()
+    }
+
+    /// Reads the replay header from `reader`, chunks are read on demand.
+    pub fn from_reader<R: Read + Seek>(mut reader: R) -> crate::Result<ReplayReader<R>> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let file_magic = reader.read_u32::<LE>()?;
+        if file_magic != FILE_MAGIC {
+            return Err(crate::ErrorKind::ReplayParseError(format!("not a replay file, magic was {:#x}", file_magic)).into());
         }
-        Ok(replay)
+        let meta = ReplayMeta::parse(&mut reader)
+            .chain_err(|| crate::ErrorKind::ReplayParseError("invalid replay info".to_string()))?;
+        let position = reader.stream_position()?;
+        Ok(ReplayReader {
+            reader,
+            file_magic,
+            meta,
+            position,
+            len
+        })
     }
-}
\ No newline at end of file
+}